@@ -0,0 +1,61 @@
+//! Compares plain alpha-beta with principal variation search, MTD(f) and aspiration windows:
+//! nodes and time to search a set of positions to the same depth, with heuristic leaf values.
+//!
+//!     cargo run --release --example algorithm_benchmark [depth]
+
+extern crate c4;
+
+use c4::c4::*;
+use c4::config::*;
+use c4::evaluator::*;
+use c4::mcts::*;
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const POSITIONS: [&'static str; 5] = ["", "3", "3322", "33224", "3243315"];
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let depth = args.get(1).and_then(|a| a.parse().ok()).unwrap_or(8);
+
+    let variants = [
+        ("alpha-beta", Algorithm::AlphaBeta, None),
+        ("alpha-beta + aspiration", Algorithm::AlphaBeta, Some(0.05)),
+        ("PVS", Algorithm::Pvs, None),
+        ("PVS + aspiration", Algorithm::Pvs, Some(0.05)),
+        ("MTD(f)", Algorithm::Mtdf, None),
+    ];
+    let mut baseline = Vec::new();
+    for &(name, algorithm, aspiration) in &variants {
+        let config = SearchConfig {
+            max_depth: depth,
+            threads: Threads::Single,
+            evaluator: Some(Arc::new(HeuristicEvaluator)),
+            algorithm: algorithm,
+            aspiration: aspiration,
+            ..SearchConfig::default()
+        };
+        let (mut nodes, mut time) = (0, Duration::from_secs(0));
+        let mut scores = Vec::new();
+        for moves in POSITIONS.iter() {
+            let (board, turn) = Board::from_moves(7, 6, &parse_moves(moves).unwrap()).unwrap();
+            let start = Instant::now();
+            let result = search(&board, turn, &config).unwrap();
+            time += start.elapsed();
+            nodes += result.nodes;
+            scores.push(result.score);
+        }
+        if baseline.is_empty() {
+            baseline = scores.clone();
+        }
+        let agrees = scores.iter().zip(&baseline).all(|(a, b)| (a - b).abs() < 1e-4);
+        println!(
+            "{:<24} {:>12} nodes {:>10.3}s  {}",
+            name,
+            nodes,
+            time.as_secs() as f64 + time.subsec_nanos() as f64 * 1e-9,
+            if agrees { "same scores" } else { "DIFFERENT SCORES" }
+        );
+    }
+}
@@ -0,0 +1,41 @@
+//! Plays each difficulty against the one below it and prints the score and the Elo gap it
+//! implies, with the ladder's total above the weakest level.
+//!
+//!     cargo run --release --example calibrate [games] [seed]
+
+extern crate c4;
+
+use c4::difficulty::*;
+use c4::seed::DEFAULT_SEED;
+use std::env;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let games = args.get(1).and_then(|a| a.parse().ok()).unwrap_or(20);
+    let seed = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(DEFAULT_SEED);
+
+    println!("{:>13} {:>13} {:>7} {:>6} {:>6}", "level", "against", "score", "elo", "total");
+    println!("{:>13} {:>13} {:>7} {:>6} {:>6}", format!("{:?}", DIFFICULTIES[0]), "", "", "", 0);
+    let mut total = 0.;
+    for pair in DIFFICULTIES.windows(2) {
+        let score = play_match(&pair[1].level(), &pair[0].level(), games, seed);
+        let elo = elo_gap(score, games);
+        total += elo;
+        println!(
+            "{:>13} {:>13} {:>6.1}% {:>6.0} {:>6.0}",
+            format!("{:?}", pair[1]),
+            format!("{:?}", pair[0]),
+            100. * score,
+            elo,
+            total
+        );
+    }
+}
+
+//the rating difference that expects `score`, which is kept half a game from 0 and 1 so a
+//clean sweep still gives a finite gap
+fn elo_gap(score: f32, games: u32) -> f32 {
+    let margin = 0.5 / games as f32;
+    let s = score.max(margin).min(1. - margin);
+    400. * (s / (1. - s)).log10()
+}
@@ -0,0 +1,46 @@
+//! Trains an N-tuple network by TD(λ) self-play, saving it after every round of games.
+//! Training continues from the file if it already exists.
+//!
+//!     cargo run --release --example ntuple_train <file> [rounds] [games per round]
+
+extern crate c4;
+
+use c4::c4::*;
+use c4::ntuple::*;
+use std::env;
+use std::path::Path;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let file = match args.get(1) {
+        Some(file) => file.clone(),
+        None => {
+            eprintln!("usage: ntuple_train <file> [rounds] [games per round]");
+            return;
+        }
+    };
+    let rounds = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(10);
+    let games = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(10000);
+
+    let mut network = if Path::new(&file).exists() {
+        NTupleNetwork::load(&file).expect("could not read the network")
+    } else {
+        NTupleNetwork::standard([1, 2, 3, 4])
+    };
+    let empty = Board::from_int_array(vec![0; 42], 7, 6);
+    for round in 0..rounds {
+        let config = TdConfig {
+            games: games,
+            seed: round + 1,
+            ..TdConfig::default()
+        };
+        network.train(&config);
+        network.save(&file).expect("could not write the network");
+        println!(
+            "round {:>3}: value of the empty board {:.3}, move values {:?}",
+            round + 1,
+            network.value(&empty, Player::P1),
+            network.move_values(&empty, Player::P1)
+        );
+    }
+}
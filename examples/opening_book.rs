@@ -0,0 +1,44 @@
+//! Generates an opening book for the standard board and writes it to a file.
+//!
+//!     cargo run --release --example opening_book <file> [plies] [solve|search]
+//!
+//! `solve` books hold perfect moves but take a long time beyond a few plies, `search` books use
+//! `SearchConfig::strong()`. Load the file with `Book::load` and set it as `SearchConfig::book`.
+
+extern crate c4;
+
+use c4::book::*;
+use c4::config::*;
+use std::env;
+use std::process;
+use std::time::Instant;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let path = match args.get(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: opening_book <file> [plies] [solve|search]");
+            process::exit(1);
+        }
+    };
+    let plies = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(4);
+
+    let start = Instant::now();
+    let book = match args.get(3).map(|a| a.as_str()).unwrap_or("solve") {
+        "solve" => Book::solved(7, 6, plies, 256 * 1024 * 1024),
+        "search" => Book::searched(7, 6, plies, &SearchConfig::strong()),
+        other => {
+            eprintln!("unknown generator {}, expected solve or search", other);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = book.save(path) {
+        eprintln!("could not write {}: {}", path, e);
+        process::exit(1);
+    }
+    println!(
+        "{} positions to ply {} in {}s, written to {}",
+        book.len(), plies, start.elapsed().as_secs(), path
+    );
+}
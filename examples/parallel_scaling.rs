@@ -0,0 +1,34 @@
+//! Measures how root-parallel search throughput scales with the number of threads.
+//!
+//!     cargo run --release --example parallel_scaling [max_threads] [millis]
+
+extern crate c4;
+extern crate rayon;
+
+use c4::c4::*;
+use c4::rayon_tree::*;
+use std::env;
+use std::time::Duration;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let max_threads = args.get(1)
+        .and_then(|a| a.parse().ok())
+        .unwrap_or(rayon::current_num_threads());
+    let millis = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(2000);
+
+    let board = Board::from_int_array(vec![0; 42], 7, 6);
+    let mut base = None;
+    let mut threads = 1;
+    while threads <= max_threads {
+        let pool = Pool::new(threads);
+        let result = pool.search(&board, Player::P1, Limit::Time(Duration::from_millis(millis)));
+        let rate = result.playouts as f64 * 1000. / millis as f64;
+        let speedup = rate / *base.get_or_insert(rate);
+        println!(
+            "{:>3} threads: {:>10.0} playouts/s  x{:.2}  best move {}",
+            threads, rate, speedup, result.best_move
+        );
+        threads *= 2;
+    }
+}
@@ -0,0 +1,35 @@
+//! Proves the value of a position with proof-number search and analyses every move.
+//! The position is given as the columns played so far, from 0, on the standard board.
+//!
+//!     cargo run --release --example prove [moves] [max nodes]
+//!     cargo run --release --example prove 3322 1000000
+
+extern crate c4;
+
+use c4::c4::*;
+use c4::pns::*;
+use std::env;
+use std::time::Instant;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let moves = args.get(1).cloned().unwrap_or_default();
+    let max_nodes = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(1000000);
+
+    let columns = parse_moves(&moves).expect("moves are columns 0 to 6");
+    let (mut board, turn) = Board::from_moves(7, 6, &columns).expect("not a legal game");
+    if columns.last().map_or(false, |&column| board.is_over(column)) {
+        eprintln!("the game is already over");
+        return;
+    }
+    board.print_board();
+
+    let mut search = ProofSearch::new(max_nodes);
+    let start = Instant::now();
+    let result = search.solve(&board, turn);
+    println!("{:?} to move: {:?}, best move {:?}", turn, result.outcome, result.best_move);
+    for (column, outcome) in search.analyse(&board, turn) {
+        println!("  {}: {:?}", column, outcome);
+    }
+    println!("{} nodes in {:?}", search.nodes(), start.elapsed());
+}
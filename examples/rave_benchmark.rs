@@ -0,0 +1,70 @@
+//! Plays RAVE against plain UCT at the same number of playouts per move, alternating colours,
+//! and compares their playout rates.
+//!
+//!     cargo run --release --example rave_benchmark [games] [playouts] [equivalence]
+
+extern crate c4;
+
+use c4::c4::*;
+use c4::uct::*;
+use std::env;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let games = args.get(1).and_then(|a| a.parse().ok()).unwrap_or(20);
+    let playouts = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(2000);
+    let equivalence = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(500.);
+
+    let (mut wins, mut draws, mut losses) = (0, 0, 0);
+    //time spent and playouts run by RAVE and by plain UCT
+    let mut time = [Duration::from_secs(0); 2];
+    let mut runs = [0; 2];
+
+    for game in 0..games {
+        let rave_player = if game % 2 == 0 { Player::P1 } else { Player::P2 };
+        let mut board = Board::from_int_array(vec![0; 42], 7, 6);
+        let mut turn = Player::P1;
+        let winner = loop {
+            let rave = turn == rave_player;
+            let seed = [game + 1, board.turn_number + 1, rave as u32 + 1, 0x2545];
+            let mut tree = UctTree::new(&board, turn, seed);
+            if rave {
+                tree.set_rave_equivalence(equivalence);
+            }
+            let start = Instant::now();
+            tree.run(playouts);
+            time[!rave as usize] += start.elapsed();
+            runs[!rave as usize] += tree.playouts();
+
+            let column = tree.best_move().expect("no move found");
+            board = board.place(column, turn).unwrap();
+            if board.is_over(column) {
+                break turn;
+            }
+            if board.turn_number >= 42 {
+                break Player::Empty;
+            }
+            turn = turn.switch();
+        };
+
+        if winner == Player::Empty {
+            draws += 1;
+        } else if winner == rave_player {
+            wins += 1;
+        } else {
+            losses += 1;
+        }
+        println!("game {:>3}: RAVE as {:?}, winner {:?}", game + 1, rave_player, winner);
+    }
+
+    let rate = |i: usize| {
+        let secs = time[i].as_secs() as f64 + time[i].subsec_nanos() as f64 * 1e-9;
+        runs[i] as f64 / secs
+    };
+    println!(
+        "RAVE (k = {}) against UCT at {} playouts per move: {} wins, {} draws, {} losses",
+        equivalence, playouts, wins, draws, losses
+    );
+    println!("playouts/s: RAVE {:.0}, UCT {:.0}", rate(0), rate(1));
+}
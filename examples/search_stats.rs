@@ -0,0 +1,58 @@
+//! Searches a position and prints where the work went, depth by depth. The position is given
+//! as the columns played so far, from 0, on the standard board.
+//!
+//!     cargo run --release --example search_stats [moves] [depth]
+
+extern crate c4;
+
+use c4::c4::*;
+use c4::config::*;
+use c4::mcts::*;
+use std::env;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let moves = args.get(1).cloned().unwrap_or_default();
+    let depth = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(6);
+
+    let columns = parse_moves(&moves).expect("moves are columns");
+    let (board, turn) = Board::from_moves(7, 6, &columns).expect("not a legal game");
+    let config = SearchConfig {
+        max_depth: depth,
+        threads: Threads::Single,
+        ..SearchConfig::default()
+    };
+    let result = search(&board, turn, &config).unwrap();
+
+    println!(
+        "{:>5} {:>10} {:>10} {:>9} {:>8} {:>7} {:>8} {:>7} {:>10}",
+        "depth", "nodes", "leaves", "rollouts", "cutoffs", "first%", "tt hits", "hit%", "time"
+    );
+    for (i, d) in result.depth_stats.iter().enumerate() {
+        println!(
+            "{:>5} {:>10} {:>10} {:>9} {:>8} {:>6.1}% {:>8} {:>6.1}% {:>10?}",
+            i + 1,
+            d.nodes,
+            d.leaves,
+            d.rollouts,
+            d.cutoffs,
+            100. * d.first_move_cutoff_rate(),
+            d.table_hits,
+            100. * d.table_hit_rate(),
+            d.time
+        );
+    }
+    let total = &result.stats;
+    println!(
+        "total: {} nodes in {:?}, {:.0} nodes/s, {} finished games, {} re-searches",
+        total.nodes,
+        total.time,
+        total.nodes_per_second(),
+        total.terminals,
+        total.re_searches
+    );
+    if let Some(ebf) = result.effective_branching_factor() {
+        println!("effective branching factor {:.2}", ebf);
+    }
+    println!("best move {} with score {:.3}", result.best_move, result.score);
+}
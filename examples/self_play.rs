@@ -0,0 +1,42 @@
+//! Trains a network from scratch by self-play, checkpointing into a directory. Running it again
+//! on the same directory resumes the run.
+//!
+//!     cargo run --release --example self_play <dir> [iterations] [width] [height]
+
+extern crate c4;
+
+use c4::training::*;
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let dir = match args.get(1) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            eprintln!("usage: self_play <dir> [iterations] [width] [height]");
+            return;
+        }
+    };
+    let iterations = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(10);
+    let defaults = TrainingConfig::default();
+    let config = TrainingConfig {
+        w: args.get(3).and_then(|a| a.parse().ok()).unwrap_or(defaults.w),
+        h: args.get(4).and_then(|a| a.parse().ok()).unwrap_or(defaults.h),
+        checkpoint_dir: Some(dir),
+        ..defaults
+    };
+
+    let mut trainer = Trainer::new(config).expect("could not open the checkpoint directory");
+    if trainer.iteration() > 0 {
+        println!("resuming after iteration {}", trainer.iteration());
+    }
+    for _ in 0..iterations {
+        let report = trainer.run_iteration().expect("could not write a checkpoint");
+        println!(
+            "iteration {:>3}: {} samples, loss {:.4}, gating score {:.3}, {}",
+            report.iteration, report.samples, report.loss, report.gating_score,
+            if report.accepted { "accepted" } else { "rejected" }
+        );
+    }
+}
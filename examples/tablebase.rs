@@ -0,0 +1,39 @@
+//! Builds the tablebase for a board in a directory, one file per stone count.
+//!
+//!     cargo run --release --example tablebase <dir> [width] [height]
+//!
+//! The default is 6x5. Open the result with `Tablebase::open` and set it as
+//! `SearchConfig::tablebase`.
+
+extern crate c4;
+
+use c4::tablebase::*;
+use std::env;
+use std::process;
+use std::time::Instant;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let dir = match args.get(1) {
+        Some(dir) => dir,
+        None => {
+            eprintln!("usage: tablebase <dir> [width] [height]");
+            process::exit(1);
+        }
+    };
+    let w = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(6);
+    let h = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(5);
+
+    let start = Instant::now();
+    let tablebase = match Tablebase::build_in(w, h, dir) {
+        Ok(tablebase) => tablebase,
+        Err(e) => {
+            eprintln!("could not build in {}: {}", dir, e);
+            process::exit(1);
+        }
+    };
+    println!(
+        "{} positions on {}x{} in {}s, written to {}",
+        tablebase.len(), w, h, start.elapsed().as_secs(), dir
+    );
+}
@@ -0,0 +1,315 @@
+use bytes::*;
+use c4::*;
+use config::SearchConfig;
+use mcts::{search, Outcome};
+use solver::*;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &'static [u8; 4] = b"C4BK";
+const VERSION: u8 = 1;
+
+/// What the book says about one position: the move to play, and the result of the game for the
+/// player to move when the book was built with a solver.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BookEntry {
+    pub column: u32,
+    pub outcome: Option<Outcome>,
+}
+
+/// Moves for every position reachable in the first `plies` moves of a game.
+///
+/// A position and its mirror image share one entry, keyed by whichever of the two has the
+/// smaller solver key, so a book holds about half the positions it covers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Book {
+    w: u32,
+    h: u32,
+    plies: u32,
+    //sorted by key
+    entries: Vec<(u64, BookEntry)>,
+}
+
+impl Book {
+    /// Builds a book by calling `pick` once for every distinct position, up to mirroring, with
+    /// fewer than `plies` stones on a `w` by `h` board. Positions `pick` returns `None` for are
+    /// left out.
+    pub fn generate(w: u32, h: u32, plies: u32, pick: &mut FnMut(&Board, Player) -> Option<BookEntry>) -> Book {
+        assert_log("board too large for a book", Position::fits(w, h));
+        let mut entries = Vec::new();
+        let mut seen = HashSet::new();
+        let mut layer = vec![(Board::from_int_array(vec![0; (w * h) as usize], w, h), Player::P1)];
+
+        for _ in 0..plies {
+            let mut next_layer = Vec::new();
+            for (board, turn) in layer {
+                let (key, mirrored) = canonical_key(&board, turn);
+                if !seen.insert(key) {
+                    continue;
+                }
+                if let Some(mut entry) = pick(&board, turn) {
+                    if mirrored {
+                        entry.column = w - 1 - entry.column;
+                    }
+                    entries.push((key, entry));
+                }
+                for column in 0..w {
+                    if let Some(mut child) = board.place(column, turn) {
+                        if !child.is_over(column) && child.turn_number < w * h {
+                            next_layer.push((child, turn.switch()));
+                        }
+                    }
+                }
+            }
+            layer = next_layer;
+        }
+
+        entries.sort_by_key(|&(key, _)| key);
+        Book {
+            w: w,
+            h: h,
+            plies: plies,
+            entries: entries,
+        }
+    }
+
+    /// A book of perfect moves with their proven outcomes. Only practical for short books on
+    /// the standard board, the first few plies there take minutes each to solve.
+    pub fn solved(w: u32, h: u32, plies: u32, table_size: usize) -> Book {
+        let mut solver = Solver::new(table_size);
+        Book::generate(w, h, plies, &mut |board, turn| {
+            let solution = solver.solve(board, turn);
+            solver.best_move(board, turn).map(|column| BookEntry {
+                column: column,
+                outcome: Some(solution.outcome),
+            })
+        })
+    }
+
+    /// A book of the moves `config` finds, with outcomes only where the search proved them.
+    pub fn searched(w: u32, h: u32, plies: u32, config: &SearchConfig) -> Book {
+        let config = SearchConfig {
+            book: None,
+            ..config.clone()
+        };
+        Book::generate(w, h, plies, &mut |board, turn| {
+            let result = search(board, turn, &config).expect("invalid search config");
+            Some(BookEntry {
+                column: result.best_move,
+                outcome: result.proven,
+            })
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.w
+    }
+
+    pub fn height(&self) -> u32 {
+        self.h
+    }
+
+    pub fn plies(&self) -> u32 {
+        self.plies
+    }
+
+    /// Number of stored positions, with mirror images counted once.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The book entry for `board` with `turn` to move, if the book covers it.
+    pub fn lookup(&self, board: &Board, turn: Player) -> Option<BookEntry> {
+        if board.w != self.w || board.h != self.h || board.turn_number >= self.plies {
+            return None;
+        }
+        let (key, mirrored) = canonical_key(board, turn);
+        self.entries
+            .binary_search_by_key(&key, |&(k, _)| k)
+            .ok()
+            .map(|i| {
+                let mut entry = self.entries[i].1;
+                if mirrored {
+                    entry.column = self.w - 1 - entry.column;
+                }
+                entry
+            })
+    }
+
+    /// Writes the book in its binary format: a header, then ten bytes per position.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION, self.w as u8, self.h as u8, self.plies as u8])?;
+        out.write_all(&u64_bytes(self.entries.len() as u64))?;
+        for &(key, entry) in &self.entries {
+            out.write_all(&u64_bytes(key))?;
+            out.write_all(&[entry.column as u8, outcome_code(entry.outcome)])?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Book> {
+        let mut header = [0; 16];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not an opening book"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid("unsupported opening book version"));
+        }
+        let (w, h, plies) = (header[5] as u32, header[6] as u32, header[7] as u32);
+        if !Position::fits(w, h) {
+            return Err(invalid("opening book board is too large"));
+        }
+        let count = from_u64_bytes(&header[8..16]);
+
+        let mut entries = Vec::new();
+        let mut record = [0; 10];
+        for _ in 0..count {
+            input.read_exact(&mut record)?;
+            let column = record[8] as u32;
+            if column >= w {
+                return Err(invalid("opening book move out of range"));
+            }
+            let outcome = match record[9] {
+                0 => None,
+                1 => Some(Outcome::Win),
+                2 => Some(Outcome::Loss),
+                3 => Some(Outcome::Draw),
+                _ => return Err(invalid("bad outcome in opening book")),
+            };
+            entries.push((from_u64_bytes(&record[..8]), BookEntry {
+                column: column,
+                outcome: outcome,
+            }));
+        }
+        if entries.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(invalid("opening book entries out of order"));
+        }
+        Ok(Book {
+            w: w,
+            h: h,
+            plies: plies,
+            entries: entries,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Book> {
+        Book::read_from(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+/// The key shared by a position and its mirror image, and whether `board` is the mirrored one.
+fn canonical_key(board: &Board, turn: Player) -> (u64, bool) {
+    let position = Position::from_board(board, turn);
+    let (key, mirror) = (position.key(), position.mirror_key());
+    if mirror < key {
+        (mirror, true)
+    } else {
+        (key, false)
+    }
+}
+
+fn outcome_code(outcome: Option<Outcome>) -> u8 {
+    match outcome {
+        None => 0,
+        Some(Outcome::Win) => 1,
+        Some(Outcome::Loss) => 2,
+        Some(Outcome::Draw) => 3,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn solved_book_matches_the_solver() {
+        let book = Book::solved(4, 4, 3, 1024 * 1024);
+        let mut solver = Solver::new(1024 * 1024);
+        for moves in &[vec![], vec![0], vec![3], vec![1, 2], vec![2, 1]] {
+            let (board, turn) = Board::from_moves(4, 4, moves).unwrap();
+            let entry = book.lookup(&board, turn).expect("position not in book");
+            let solution = solver.solve(&board, turn);
+            assert_eq!(entry.outcome, Some(solution.outcome));
+
+            //the book move keeps the value of the position
+            let mut after = board.place(entry.column, turn).unwrap();
+            if !after.is_over(entry.column) {
+                assert_eq!(solver.solve(&after, turn.switch()).score, -solution.score);
+            }
+        }
+    }
+
+    #[test]
+    fn folds_mirror_images() {
+        let book = Book::solved(4, 4, 3, 1024 * 1024);
+        //the empty board, two first moves up to mirroring, and four replies to each
+        assert_eq!(book.len(), 1 + 2 + 8);
+
+        let (left, turn) = Board::from_moves(4, 4, &[0, 1]).unwrap();
+        let (right, _) = Board::from_moves(4, 4, &[3, 2]).unwrap();
+        let a = book.lookup(&left, turn).unwrap();
+        let b = book.lookup(&right, turn).unwrap();
+        assert_eq!(a.column, 3 - b.column);
+        assert_eq!(a.outcome, b.outcome);
+    }
+
+    #[test]
+    fn only_covers_its_own_plies_and_size() {
+        let book = Book::solved(4, 4, 2, 1024 * 1024);
+        let (board, turn) = Board::from_moves(4, 4, &[0, 1]).unwrap();
+        assert!(book.lookup(&board, turn).is_none());
+        let (board, turn) = Board::from_moves(5, 4, &[]).unwrap();
+        assert!(book.lookup(&board, turn).is_none());
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let book = Book::solved(5, 4, 4, 1024 * 1024);
+        let mut bytes = Vec::new();
+        book.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 16 + 10 * book.len());
+        assert_eq!(Book::read_from(&mut &bytes[..]).unwrap(), book);
+
+        bytes[0] = b'X';
+        assert!(Book::read_from(&mut &bytes[..]).is_err());
+        assert!(Book::read_from(&mut &bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn search_plays_from_the_book() {
+        let book = Book::solved(5, 4, 3, 1024 * 1024);
+        let (board, turn) = Board::from_moves(5, 4, &[1]).unwrap();
+        let entry = book.lookup(&board, turn).unwrap();
+
+        let config = SearchConfig {
+            book: Some(Arc::new(book)),
+            ..SearchConfig::default()
+        };
+        let result = search(&board, turn, &config).unwrap();
+        assert!(result.precomputed);
+        assert_eq!(result.best_move, entry.column);
+        assert_eq!(result.nodes, 0);
+
+        //out of book, the search runs as usual
+        let (board, turn) = Board::from_moves(5, 4, &[1, 1, 2]).unwrap();
+        let result = search(&board, turn, &config).unwrap();
+        assert!(!result.precomputed);
+        assert!(result.nodes > 0);
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::{self, Read};
+
+//little-endian helpers for the book, network, n-tuple network and replay buffer files
+
+pub fn u32_bytes(x: u32) -> [u8; 4] {
+    [x as u8, (x >> 8) as u8, (x >> 16) as u8, (x >> 24) as u8]
+}
+
+pub fn u64_bytes(x: u64) -> [u8; 8] {
+    let mut bytes = [0; 8];
+    for i in 0..8 {
+        bytes[i] = (x >> (8 * i)) as u8;
+    }
+    bytes
+}
+
+pub fn from_u32_bytes(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |x, &b| x << 8 | b as u32)
+}
+
+pub fn from_u64_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |x, &b| x << 8 | b as u64)
+}
+
+pub fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(from_u32_bytes(&bytes))
+}
+
+pub fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(from_u64_bytes(&bytes))
+}
+
+pub fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(u32_bytes(0x0403_0201), [1, 2, 3, 4]);
+        assert_eq!(from_u32_bytes(&u32_bytes(0xdead_beef)), 0xdead_beef);
+        assert_eq!(from_u64_bytes(&u64_bytes(0x0123_4567_89ab_cdef)), 0x0123_4567_89ab_cdef);
+        assert_eq!(read_u32(&mut &[1, 0, 0, 0, 9][..]).unwrap(), 1);
+        assert!(read_u32(&mut &[1, 0][..]).is_err());
+        assert_eq!(read_u64(&mut &u64_bytes(7)[..]).unwrap(), 7);
+    }
+}
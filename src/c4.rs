@@ -1,3 +1,5 @@
+use seed::mix;
+
 #[derive(Clone)]
 pub struct Board {
     pub w: u32,
@@ -173,6 +175,37 @@ impl Board {
         Board::new(board_array_player, turn_number, w, h)
     }
 
+    /// The position after `moves`, columns counted from 0, on an empty `w` by `h` board, and
+    /// the player to move. P1 moves first. `None` if a move is illegal or comes after the game
+    /// was won.
+    pub fn from_moves(w: u32, h: u32, moves: &[u32]) -> Option<(Board, Player)> {
+        let mut board = Board::from_int_array(vec![0; (w * h) as usize], w, h);
+        let mut turn = Player::P1;
+        let mut over = false;
+        for &column in moves {
+            if over || column >= w {
+                return None;
+            }
+            board = board.place(column, turn)?;
+            over = board.is_over(column);
+            turn = turn.switch();
+        }
+        Some((board, turn))
+    }
+
+    /// A hash of the board size and the pieces on it, for keying transposition tables.
+    pub fn hash(&self) -> u64 {
+        let mut hash = mix(((self.w as u64) << 32) | self.h as u64);
+        for (i, place) in self.vector.iter().enumerate() {
+            match *place {
+                Player::P1 => hash ^= mix(2 * i as u64 + 1),
+                Player::P2 => hash ^= mix(2 * i as u64 + 2),
+                Player::Empty => (),
+            }
+        }
+        hash
+    }
+
     pub fn print_board(&self) {
         for i in (0..self.h).rev() {
             for k in 0..self.w {
@@ -202,6 +235,16 @@ impl Player {
             Player::Empty => Player::Empty,
         }
     }
+
+    /// 1 for P1, -1 for P2 and 0 for nobody, to turn a score for this player into one from
+    /// P1's point of view and back.
+    pub(crate) fn sign(&self) -> f32 {
+        match *self {
+            Player::P1 => 1.,
+            Player::P2 => -1.,
+            Player::Empty => 0.,
+        }
+    }
 }
 
 fn clamp(a: i32, b: u32) -> u32 {
@@ -214,6 +257,12 @@ fn clamp(a: i32, b: u32) -> u32 {
     }
 }
 
+/// Reads moves written as digits, one per column counted from 0, such as "3342". `None` if a
+/// character is not a digit.
+pub fn parse_moves(moves: &str) -> Option<Vec<u32>> {
+    moves.chars().map(|c| c.to_digit(10)).collect()
+}
+
 fn find_bounds_for_line(a: i32, bound: u32, length: u32) -> (u32,u32){
     let diff = (length - 1) as i32;
     let earliest = clamp(a - diff, bound - 1);
@@ -224,6 +273,7 @@ fn find_bounds_for_line(a: i32, bound: u32, length: u32) -> (u32,u32){
 #[cfg(test)]
 mod test {
     use super::*;
+    use tablebase::Tablebase;
 
     #[test]
     fn place() {
@@ -237,6 +287,24 @@ mod test {
         }
     }
 
+    #[test]
+    fn from_moves() {
+        let (mut board, turn) = Board::from_moves(4, 4, &[1, 1, 2]).unwrap();
+        assert_eq!((board.get(1, 0), board.get(1, 1), board.get(2, 0)), (Player::P1, Player::P2, Player::P1));
+        assert_eq!((board.turn_number, turn), (3, Player::P2));
+        assert!(!board.is_over(2));
+
+        assert!(Board::from_moves(4, 1, &[0, 0]).is_none());
+        assert!(Board::from_moves(4, 4, &[4]).is_none());
+        //nothing may follow a win
+        assert!(Board::from_moves(4, 4, &[0, 1, 0, 1, 0, 1, 0]).is_some());
+        assert!(Board::from_moves(4, 4, &[0, 1, 0, 1, 0, 1, 0, 1]).is_none());
+
+        assert_eq!(parse_moves("3042"), Some(vec![3, 0, 4, 2]));
+        assert_eq!(parse_moves(""), Some(vec![]));
+        assert_eq!(parse_moves("3,4"), None);
+    }
+
     #[test]
     fn find_column_floor() {
         let mut b = Board::from_int_array(vec![0; 49], 7, 7);
@@ -296,6 +364,25 @@ mod test {
 
     }
 
+    //every move from every 4x4 game still in progress
+    #[test]
+    fn is_over_agrees_with_tablebase() {
+        let tablebase = Tablebase::build(4, 4);
+        for position in tablebase.positions() {
+            let turn = if position.moves() % 2 == 0 { Player::P1 } else { Player::P2 };
+            let board = position.to_board(turn);
+            for x in 0..4 {
+                if let Some(mut next) = board.place(x, turn) {
+                    if next.turn_number < 16 {
+                        assert_eq!(next.is_over(x), tablebase.solve(&next, turn.switch()).is_none());
+                    } else {
+                        assert_eq!(next.is_over(x), position.is_winning_move(x));
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn bounds_test(){
         bounds_case(1, 4, 4, 7, 4);
@@ -0,0 +1,248 @@
+use book::Book;
+use evaluator::Evaluator;
+use ordering::*;
+use tablebase::Tablebase;
+use transposition::DEFAULT_TABLE_SIZE;
+use seed::DEFAULT_SEED;
+
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Everything that controls the strength and cost of a search.
+///
+/// Scores run from -1 (P2 wins) to 1 (P1 wins) and are multiplied by `discount` for every ply
+/// they are backed up, so quicker wins score higher. `score_bound` must lie outside that range;
+/// it seeds the alpha-beta window and the best score before any move has been searched.
+///
+/// `threads` picks where rollouts run. `Threads::Single` keeps the whole search on the calling
+/// thread, for hosting many games on one machine or for reproducible runs.
+///
+/// `tree_plies` is how many plies of the search tree are kept in memory once searched, so that
+/// an `engine::Engine` can reuse them on its next move. Deeper nodes are rebuilt when visited.
+///
+/// Positions covered by the `book` or the `tablebase` are answered from them without searching,
+/// the book first.
+///
+/// With an `evaluator`, leaves are scored by it instead of by `rollouts` random rollouts. The
+/// leaves below each node are evaluated together in one batch.
+///
+/// Every rollout draws from its own random stream, derived from `seed`, the position and the
+/// rollout's number. Without a time limit, the same seed and settings give the same result
+/// however many threads run the rollouts.
+///
+/// `algorithm` picks how windows are used, see `Algorithm`. With an `aspiration` window, each
+/// root move is first searched in that distance around its score from the previous depth, and
+/// again with the full window only if its score falls outside.
+#[derive(Clone, Debug)]
+pub struct SearchConfig {
+    pub max_depth: i32,
+    pub discount: f32,
+    pub rollouts: u32,
+    pub score_bound: f32,
+    pub time: Option<Duration>,
+    pub nodes: Option<u64>,
+    pub table_size: usize,
+    pub tree_plies: u32,
+    pub ordering: MoveOrdering,
+    pub threads: Threads,
+    pub book: Option<Arc<Book>>,
+    pub tablebase: Option<Arc<Tablebase>>,
+    pub evaluator: Option<Arc<Evaluator>>,
+    pub algorithm: Algorithm,
+    pub aspiration: Option<f32>,
+    pub seed: u64,
+}
+
+/// The search algorithm. All three give every root move the same exact score; they differ in
+/// how many nodes that takes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Algorithm {
+    /// Alpha-beta with the window passed down unchanged.
+    AlphaBeta,
+    /// Principal variation search: after the first move of a node, moves are searched with a
+    /// null window that only asks whether they are better, and again in full if they are.
+    Pvs,
+    /// MTD(f): each root move is searched with a series of null windows that close in on its
+    /// score, starting from the previous depth's score. Relies on the transposition table.
+    Mtdf,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Threads {
+    /// Rayon's global pool, shared with everything else in the process.
+    Global,
+    /// No extra threads at all.
+    Single,
+    /// A pool of this many threads owned by the search.
+    Count(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    MaxDepth(i32),
+    Discount(f32),
+    Rollouts(u32),
+    ScoreBound(f32),
+    TableSize(usize),
+    Threads(usize),
+    Aspiration(f32),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::MaxDepth(d) => write!(f, "max_depth must be at least 1, got {}", d),
+            ConfigError::Discount(d) => write!(f, "discount must be in (0, 1], got {}", d),
+            ConfigError::Rollouts(n) => write!(f, "rollouts must be at least 1, got {}", n),
+            ConfigError::ScoreBound(b) => write!(f, "score_bound must be greater than 1, got {}", b),
+            ConfigError::TableSize(s) => write!(f, "table_size must be non-zero, got {}", s),
+            ConfigError::Threads(n) => write!(f, "thread count must be at least 1, got {}", n),
+            ConfigError::Aspiration(w) => write!(f, "aspiration window must be positive, got {}", w),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn description(&self) -> &str {
+        "invalid search configuration"
+    }
+}
+
+impl SearchConfig {
+    /// A shallow search for quick replies and weak opponents.
+    pub fn fast() -> SearchConfig {
+        SearchConfig {
+            max_depth: 3,
+            rollouts: 5,
+            table_size: 1024 * 1024,
+            ..SearchConfig::default()
+        }
+    }
+
+    /// A deeper search for strong play at a few seconds per move.
+    pub fn strong() -> SearchConfig {
+        SearchConfig {
+            max_depth: 8,
+            rollouts: 20,
+            time: Some(Duration::from_secs(5)),
+            table_size: 64 * 1024 * 1024,
+            ..SearchConfig::default()
+        }
+    }
+
+    /// Searches as deep as it can until the time runs out.
+    pub fn analysis(time: Duration) -> SearchConfig {
+        SearchConfig {
+            max_depth: 64,
+            rollouts: 20,
+            time: Some(time),
+            table_size: 256 * 1024 * 1024,
+            ..SearchConfig::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_depth < 1 {
+            return Err(ConfigError::MaxDepth(self.max_depth));
+        }
+        if !(self.discount > 0. && self.discount <= 1.) {
+            return Err(ConfigError::Discount(self.discount));
+        }
+        if self.rollouts < 1 {
+            return Err(ConfigError::Rollouts(self.rollouts));
+        }
+        if !(self.score_bound > 1.) {
+            return Err(ConfigError::ScoreBound(self.score_bound));
+        }
+        if self.table_size == 0 {
+            return Err(ConfigError::TableSize(self.table_size));
+        }
+        if self.threads == Threads::Count(0) {
+            return Err(ConfigError::Threads(0));
+        }
+        if let Some(w) = self.aspiration {
+            if !(w > 0.) {
+                return Err(ConfigError::Aspiration(w));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for SearchConfig {
+    /// The settings `mcts::mcts` has always used: depth 5, a 0.9 discount and 10 rollouts.
+    fn default() -> SearchConfig {
+        SearchConfig {
+            max_depth: 5,
+            discount: 0.9,
+            rollouts: 10,
+            score_bound: 2.0,
+            time: None,
+            nodes: None,
+            table_size: DEFAULT_TABLE_SIZE,
+            tree_plies: 4,
+            ordering: MoveOrdering::default(),
+            threads: Threads::Global,
+            book: None,
+            tablebase: None,
+            evaluator: None,
+            algorithm: Algorithm::AlphaBeta,
+            aspiration: None,
+            seed: DEFAULT_SEED,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid() {
+        assert_eq!(SearchConfig::default().validate(), Ok(()));
+        assert_eq!(SearchConfig::fast().validate(), Ok(()));
+        assert_eq!(SearchConfig::strong().validate(), Ok(()));
+        assert_eq!(SearchConfig::analysis(Duration::from_secs(1)).validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        let bad_depth = SearchConfig {
+            max_depth: 0,
+            ..SearchConfig::default()
+        };
+        assert_eq!(bad_depth.validate(), Err(ConfigError::MaxDepth(0)));
+
+        let bad_discount = SearchConfig {
+            discount: 1.5,
+            ..SearchConfig::default()
+        };
+        assert_eq!(bad_discount.validate(), Err(ConfigError::Discount(1.5)));
+
+        let bad_rollouts = SearchConfig {
+            rollouts: 0,
+            ..SearchConfig::default()
+        };
+        assert_eq!(bad_rollouts.validate(), Err(ConfigError::Rollouts(0)));
+
+        let bad_bound = SearchConfig {
+            score_bound: 1.0,
+            ..SearchConfig::default()
+        };
+        assert_eq!(bad_bound.validate(), Err(ConfigError::ScoreBound(1.0)));
+
+        let bad_threads = SearchConfig {
+            threads: Threads::Count(0),
+            ..SearchConfig::default()
+        };
+        assert_eq!(bad_threads.validate(), Err(ConfigError::Threads(0)));
+
+        let bad_aspiration = SearchConfig {
+            aspiration: Some(0.),
+            ..SearchConfig::default()
+        };
+        assert_eq!(bad_aspiration.validate(), Err(ConfigError::Aspiration(0.)));
+    }
+}
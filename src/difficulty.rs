@@ -0,0 +1,256 @@
+use c4::*;
+use config::*;
+use evaluator::*;
+use mcts::*;
+use seed;
+
+use rand::{Rng, XorShiftRng};
+use std::sync::Arc;
+
+/// Preset playing strengths.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Difficulty {
+    Beginner,
+    Casual,
+    Intermediate,
+    Advanced,
+    Expert,
+}
+
+/// Every difficulty, weakest first.
+pub const DIFFICULTIES: [Difficulty; 5] = [
+    Difficulty::Beginner,
+    Difficulty::Casual,
+    Difficulty::Intermediate,
+    Difficulty::Advanced,
+    Difficulty::Expert,
+];
+
+/// How a player is weakened. Each weakness sits on top of an ordinary search with heuristic
+/// leaf values, so the mistakes look like a person's: not seeing far enough ahead, misjudging
+/// positions, now and then overlooking a threat, and not always playing the move it likes best.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub depth: i32,
+    /// The most error added to the value of each position searched, on the -1 to 1 scale.
+    pub noise: f32,
+    /// The chance each move of overlooking the threats on the board and choosing by the shape
+    /// of the position alone. A win on the spot is never missed, but the block of one can be.
+    pub missed_threats: f32,
+    /// Moves are chosen with probability proportional to `exp(score / temperature)`, so higher
+    /// temperatures stray further from the best move. 0 always plays the best move.
+    pub temperature: f32,
+}
+
+impl Difficulty {
+    pub fn level(self) -> Level {
+        let (depth, noise, missed_threats, temperature) = match self {
+            Difficulty::Beginner => (1, 0.4, 0.25, 0.1),
+            Difficulty::Casual => (2, 0.25, 0.12, 0.05),
+            Difficulty::Intermediate => (4, 0.1, 0.05, 0.02),
+            Difficulty::Advanced => (6, 0.04, 0.01, 0.005),
+            Difficulty::Expert => (8, 0., 0., 0.),
+        };
+        Level {
+            depth: depth,
+            noise: noise,
+            missed_threats: missed_threats,
+            temperature: temperature,
+        }
+    }
+}
+
+/// A player at a given `Level`. Its choices are random, but the same `seed` repeats them.
+pub struct WeakPlayer {
+    level: Level,
+    seed: u64,
+    rng: XorShiftRng,
+    moves: u64,
+}
+
+impl WeakPlayer {
+    pub fn new(level: Level, seed: u64) -> WeakPlayer {
+        WeakPlayer {
+            level: level,
+            seed: seed,
+            rng: seed::rng(seed, 0),
+            moves: 0,
+        }
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    /// Picks a move for `turn`. Panics if there is no legal move.
+    pub fn choose_move(&mut self, board: &Board, turn: Player) -> u32 {
+        self.moves += 1;
+        let missed = self.level.missed_threats > 0. && self.rng.gen_range(0., 1.) < self.level.missed_threats;
+        //wins and blocks on the spot are played outright, not left to noise and temperature
+        if let Some(column) = (0..board.w).find(|&c| wins_at(board, turn, c)) {
+            return column;
+        }
+        if missed {
+            //the heuristic ignores finished lines, so the opponent's next win goes unseen
+            let scores: Vec<(u32, f32)> = (0..board.w)
+                .filter_map(|column| {
+                    board.place(column, turn).map(|mut next| {
+                        if next.is_over(column) {
+                            (column, 1.)
+                        } else if next.turn_number >= next.w * next.h {
+                            (column, 0.)
+                        } else {
+                            (column, -HeuristicEvaluator.evaluate(&next, turn.switch()).value)
+                        }
+                    })
+                })
+                .collect();
+            assert_log("no legal move", !scores.is_empty());
+            return self.pick(&scores);
+        }
+        let threats: Vec<u32> = (0..board.w).filter(|&c| wins_at(board, turn.switch(), c)).collect();
+        if threats.len() == 1 {
+            return threats[0];
+        }
+
+        let result = search(board, turn, &self.config()).expect("difficulty levels are valid");
+        let scores: Vec<(u32, f32)> = result.move_scores.iter().map(|m| (m.column, turn.sign() * m.score)).collect();
+        self.pick(&scores)
+    }
+
+    fn config(&self) -> SearchConfig {
+        let evaluator: Arc<Evaluator> = if self.level.noise > 0. {
+            //a fresh misjudgement every move, consistent within its search
+            Arc::new(NoisyEvaluator {
+                inner: Arc::new(HeuristicEvaluator),
+                noise: self.level.noise,
+                seed: seed::derive(self.seed, self.moves),
+            })
+        } else {
+            Arc::new(HeuristicEvaluator)
+        };
+        SearchConfig {
+            max_depth: self.level.depth,
+            table_size: 1 << 18,
+            threads: Threads::Single,
+            evaluator: Some(evaluator),
+            seed: self.seed,
+            ..SearchConfig::default()
+        }
+    }
+
+    //a softmax draw over the scores for the player to move, the first best one at temperature 0
+    fn pick(&mut self, scores: &[(u32, f32)]) -> u32 {
+        let best = scores.iter().fold(scores[0], |best, &m| if m.1 > best.1 { m } else { best });
+        if self.level.temperature <= 0. {
+            return best.0;
+        }
+        let weights: Vec<f32> = scores
+            .iter()
+            .map(|&(_, s)| ((s - best.1) / self.level.temperature).exp())
+            .collect();
+        let mut x = self.rng.gen_range(0., weights.iter().sum::<f32>());
+        for (&(column, _), &w) in scores.iter().zip(&weights) {
+            if x < w {
+                return column;
+            }
+            x -= w;
+        }
+        best.0
+    }
+}
+
+/// Plays `games` games on the standard board between a player at level `a` and one at level
+/// `b`, alternating who starts, and returns `a`'s score: 1 per win and a half per draw, over the
+/// number of games.
+pub fn play_match(a: &Level, b: &Level, games: u32, seed: u64) -> f32 {
+    let mut score = 0.;
+    for game in 0..games {
+        let game_seed = seed::derive(seed, game as u64);
+        let mut players = [
+            WeakPlayer::new(a.clone(), seed::derive(game_seed, 1)),
+            WeakPlayer::new(b.clone(), seed::derive(game_seed, 2)),
+        ];
+        //players[a_index] is P1
+        let a_index = if game % 2 == 0 { 0 } else { 1 };
+        let mut board = Board::from_int_array(vec![0; 42], 7, 6);
+        let mut turn = Player::P1;
+        let winner = loop {
+            let mover = if turn == Player::P1 { a_index } else { 1 - a_index };
+            let column = players[mover].choose_move(&board, turn);
+            board = board.place(column, turn).unwrap();
+            if board.is_over(column) {
+                break Some(mover);
+            }
+            if board.turn_number >= 42 {
+                break None;
+            }
+            turn = turn.switch();
+        };
+        score += match winner {
+            Some(0) => 1.,
+            None => 0.5,
+            _ => 0.,
+        };
+    }
+    score / games as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn levels_weaken_step_by_step() {
+        for pair in DIFFICULTIES.windows(2) {
+            let (weaker, stronger) = (pair[0].level(), pair[1].level());
+            assert!(weaker.depth < stronger.depth);
+            assert!(weaker.noise > stronger.noise);
+            assert!(weaker.missed_threats > stronger.missed_threats);
+            assert!(weaker.temperature > stronger.temperature);
+        }
+    }
+
+    #[test]
+    fn expert_wins_and_blocks() {
+        let mut expert = WeakPlayer::new(Difficulty::Expert.level(), 1);
+        let (block, p2) = Board::from_moves(7, 6, &[0, 6, 1, 6, 2]).unwrap();
+        assert_eq!(expert.choose_move(&block, p2), 3);
+        let (win, p1) = Board::from_moves(7, 6, &[0, 6, 1, 6, 2, 5]).unwrap();
+        assert_eq!(expert.choose_move(&win, p1), 3);
+    }
+
+    #[test]
+    fn beginners_take_wins_but_sometimes_miss_blocks() {
+        let (win, p1) = Board::from_moves(7, 6, &[0, 6, 1, 6, 2, 5]).unwrap();
+        let (block, p2) = Board::from_moves(7, 6, &[0, 6, 1, 6, 2]).unwrap();
+        let mut taken = 0;
+        for seed in 0..100 {
+            let mut beginner = WeakPlayer::new(Difficulty::Beginner.level(), seed);
+            assert_eq!(beginner.choose_move(&win, p1), 3);
+            if beginner.choose_move(&block, p2) == 3 {
+                taken += 1;
+            }
+        }
+        //blocks are only missed along with every other threat, and even then the shape of the
+        //position often suggests them
+        assert!(taken > 80 && taken < 100, "{}", taken);
+    }
+
+    #[test]
+    fn seeds_repeat_choices() {
+        let (board, turn) = Board::from_moves(7, 6, &[3, 3, 2]).unwrap();
+        let choices = |seed| {
+            let mut player = WeakPlayer::new(Difficulty::Casual.level(), seed);
+            (0..10).map(|_| player.choose_move(&board, turn)).collect::<Vec<u32>>()
+        };
+        assert_eq!(choices(1), choices(1));
+        assert!(choices(1).iter().any(|&c| c != choices(1)[0]));
+    }
+
+    #[test]
+    fn stronger_levels_win() {
+        let score = play_match(&Difficulty::Intermediate.level(), &Difficulty::Beginner.level(), 4, 1);
+        assert!(score >= 0.75, "{}", score);
+    }
+}
@@ -0,0 +1,311 @@
+use c4::*;
+use tree::*;
+use mcts::*;
+use config::*;
+use transposition::*;
+use rayon_tree::Pool;
+use cancel::CancelToken;
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::{self, JoinHandle};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlayError {
+    ColumnFull(u32),
+    OutOfRange(u32),
+    GameOver,
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PlayError::ColumnFull(c) => write!(f, "column {} is full", c),
+            PlayError::OutOfRange(c) => write!(f, "column {} is off the board", c),
+            PlayError::GameOver => write!(f, "the game is already over"),
+        }
+    }
+}
+
+impl Error for PlayError {
+    fn description(&self) -> &str {
+        "illegal move"
+    }
+}
+
+/// A search that lives for a whole game. The search tree and transposition table are kept
+/// between moves; playing a move makes the matching subtree the new root and drops the rest.
+///
+/// While the opponent thinks, `start_pondering` keeps searching the current position on a
+/// worker thread. Any other call stops the worker first and takes the tree and table back, so
+/// the opponent's move lands on a warm subtree.
+pub struct Engine {
+    config: SearchConfig,
+    board: Board,
+    turn: Player,
+    root: Option<InnerNode>,
+    winner: Option<Player>,
+    table: TranspositionTable,
+    pool: Option<Arc<Pool>>,
+    ponder: Option<Ponder>,
+    ponder_result: Option<SearchResult>,
+}
+
+struct Ponder {
+    cancel: CancelToken,
+    results: Receiver<SearchResult>,
+    handle: JoinHandle<(InnerNode, TranspositionTable)>,
+}
+
+impl Engine {
+    pub fn new(board: Board, turn: Player, config: SearchConfig) -> Result<Engine, ConfigError> {
+        config.validate()?;
+        Ok(Engine {
+            table: TranspositionTable::new(config.table_size),
+            pool: match config.threads {
+                Threads::Count(n) => Some(Arc::new(Pool::new(n))),
+                _ => None,
+            },
+            root: Some(InnerNode::new(board.clone(), turn)),
+            board: board,
+            turn: turn,
+            winner: None,
+            config: config,
+            ponder: None,
+            ponder_result: None,
+        })
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn turn(&self) -> Player {
+        self.turn
+    }
+
+    /// `Some(Player::Empty)` for a draw, `None` while the game is still going.
+    pub fn winner(&self) -> Option<Player> {
+        self.winner
+    }
+
+    /// Searches the current position for the player to move, or answers from the configured
+    /// book or tablebase. Returns `None` once the game is over.
+    pub fn search(&mut self) -> Option<SearchResult> {
+        self.search_with(&CancelToken::new(), &mut |_| ())
+    }
+
+    /// Like `search`, but can be cancelled through `cancel` and reports to `progress` as
+    /// `mcts::search_with` does. Also returns `None` if cancelled before depth 1 completed.
+    pub fn search_with(&mut self, cancel: &CancelToken, progress: &mut FnMut(&Progress)) -> Option<SearchResult> {
+        self.stop_pondering();
+        match self.root {
+            Some(ref mut root) => {
+                if let Some(result) = precomputed_result(&root.board, root.turn, &self.config) {
+                    return Some(result);
+                }
+                let pool = self.pool.as_ref().map(|p| &**p);
+                deepen(root, &self.config, &mut self.table, pool, Some(cancel), Some(progress), |_| ())
+            }
+            None => None,
+        }
+    }
+
+    /// Starts searching the current position in the background, with no time or node limit.
+    /// Does nothing if the engine is already pondering or the game is over.
+    pub fn start_pondering(&mut self) {
+        if self.ponder.is_some() {
+            return;
+        }
+        let mut root = match self.root.take() {
+            Some(root) => root,
+            None => return,
+        };
+        //the worker owns the table until it is joined, leave a one-entry stand-in behind
+        let mut table = mem::replace(&mut self.table, TranspositionTable::new(0));
+        let config = SearchConfig {
+            time: None,
+            nodes: None,
+            ..self.config.clone()
+        };
+        let cancel = CancelToken::new();
+        let worker_cancel = cancel.clone();
+        let (sender, results) = channel();
+        let pool = self.pool.clone();
+
+        let handle = thread::spawn(move || {
+            let pool = pool.as_ref().map(|p| &**p);
+            deepen(&mut root, &config, &mut table, pool, Some(&worker_cancel), None, |r| {
+                let _ = sender.send(r.clone());
+            });
+            (root, table)
+        });
+
+        self.ponder_result = None;
+        self.ponder = Some(Ponder {
+            cancel: cancel,
+            results: results,
+            handle: handle,
+        });
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.ponder.is_some()
+    }
+
+    /// The deepest result the background search has completed so far.
+    pub fn ponder_result(&mut self) -> Option<SearchResult> {
+        if let Some(ref ponder) = self.ponder {
+            while let Ok(r) = ponder.results.try_recv() {
+                self.ponder_result = Some(r);
+            }
+        }
+        self.ponder_result.clone()
+    }
+
+    /// Cancels the background search, waits for it to wind down and returns its deepest result.
+    pub fn stop_pondering(&mut self) -> Option<SearchResult> {
+        let ponder = match self.ponder.take() {
+            Some(ponder) => ponder,
+            None => return None,
+        };
+        ponder.cancel.cancel();
+        let (root, table) = ponder.handle.join().expect("pondering thread panicked");
+        while let Ok(r) = ponder.results.try_recv() {
+            self.ponder_result = Some(r);
+        }
+        self.root = Some(root);
+        self.table = table;
+        self.ponder_result.take()
+    }
+
+    /// Plays `column` for the player to move.
+    pub fn play(&mut self, column: u32) -> Result<(), PlayError> {
+        self.stop_pondering();
+        let mut root = match self.root.take() {
+            Some(root) => root,
+            None => return Err(PlayError::GameOver),
+        };
+        if column >= self.board.w {
+            self.root = Some(root);
+            return Err(PlayError::OutOfRange(column));
+        }
+        if root.children.is_empty() {
+            root.find_children();
+        }
+        let i = match root.children.iter().position(|c| c.input == column) {
+            Some(i) => i,
+            None => {
+                self.root = Some(root);
+                return Err(PlayError::ColumnFull(column));
+            }
+        };
+
+        let branch = root.children.swap_remove(i);
+        self.board = self.board.place(column, self.turn).expect("column has room");
+        self.turn = self.turn.switch();
+        match branch.node {
+            Node::InnerNode(node) => self.root = Some(node),
+            Node::Leaf(leaf) => self.winner = Some(leaf.winner),
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop_pondering();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::time::{Duration, Instant};
+
+    fn config() -> SearchConfig {
+        SearchConfig {
+            max_depth: 4,
+            table_size: 1024 * 1024,
+            ..SearchConfig::default()
+        }
+    }
+
+    #[test]
+    fn reuses_work_across_moves() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let mut engine = Engine::new(board, Player::P1, config()).unwrap();
+
+        let first = engine.search().unwrap();
+        engine.play(first.principal_variation[0]).unwrap();
+        engine.play(first.principal_variation[1]).unwrap();
+        let warm = engine.search().unwrap();
+
+        let cold = search(engine.board(), engine.turn(), &config()).unwrap();
+        assert!(warm.nodes < cold.nodes);
+    }
+
+    #[test]
+    fn pondering_warms_the_reply() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let mut engine = Engine::new(board, Player::P1, config()).unwrap();
+        engine.play(3).unwrap();
+
+        engine.start_pondering();
+        assert!(engine.is_pondering());
+        let start = Instant::now();
+        while engine.ponder_result().map(|r| r.depth) != Some(4) {
+            assert!(start.elapsed() < Duration::from_secs(60), "pondering never finished");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        engine.play(3).unwrap();
+        assert!(!engine.is_pondering());
+        let warm = engine.search().unwrap();
+        let cold = search(engine.board(), engine.turn(), &config()).unwrap();
+        assert!(warm.nodes < cold.nodes);
+    }
+
+    #[test]
+    fn stop_pondering_mid_search() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let deep = SearchConfig {
+            max_depth: 12,
+            time: Some(Duration::from_millis(200)),
+            ..config()
+        };
+        let mut engine = Engine::new(board, Player::P1, deep).unwrap();
+
+        engine.start_pondering();
+        engine.stop_pondering();
+        assert!(!engine.is_pondering());
+        assert!(engine.stop_pondering().is_none());
+
+        engine.play(3).unwrap();
+        engine.start_pondering();
+        let result = engine.search().unwrap();
+        assert!(result.depth >= 1);
+    }
+
+    #[test]
+    fn tracks_the_game() {
+        let board = Board::from_int_array(vec![1, 1, 1, 0, 0], 5, 1);
+        let mut engine = Engine::new(board, Player::P2, config()).unwrap();
+
+        assert_eq!(engine.play(5), Err(PlayError::OutOfRange(5)));
+        assert_eq!(engine.play(0), Err(PlayError::ColumnFull(0)));
+
+        engine.play(3).unwrap();
+        assert_eq!(engine.turn(), Player::P1);
+        assert_eq!(engine.winner(), None);
+
+        engine.play(4).unwrap();
+        assert_eq!(engine.winner(), Some(Player::Empty));
+        assert!(engine.search().is_none());
+        assert_eq!(engine.play(0), Err(PlayError::GameOver));
+    }
+}
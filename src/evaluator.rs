@@ -0,0 +1,238 @@
+use c4::*;
+use rollout::*;
+use seed;
+use transposition::position_key;
+
+use rand::Rng;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// A value estimate and move priors for one position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    /// From -1, a sure loss for the player to move, to 1, a sure win.
+    pub value: f32,
+    /// One entry per column, summing to 1 over the legal moves and 0 for full columns.
+    pub priors: Vec<f32>,
+}
+
+/// Anything that can judge a position without searching it: rollouts, a hand-written heuristic
+/// or a learned model. `uct::UctTree::set_evaluator` runs PUCT on top of one, and
+/// `SearchConfig::evaluator` scores the alpha-beta search's leaves with one.
+pub trait Evaluator: Send + Sync + Debug {
+    /// Evaluates `board` for `turn`, the player to move. The game must not be over.
+    fn evaluate(&self, board: &Board, turn: Player) -> Evaluation;
+
+    /// Evaluates many positions at once. Worth overriding where a batch is cheaper than the
+    /// same positions one by one.
+    fn evaluate_batch(&self, positions: &[(&Board, Player)]) -> Vec<Evaluation> {
+        positions.iter().map(|&(board, turn)| self.evaluate(board, turn)).collect()
+    }
+}
+
+/// Values from the average of random rollouts, with uniform priors. The rollouts of each
+/// position draw from their own streams of `seed`.
+#[derive(Clone, Debug)]
+pub struct RolloutEvaluator {
+    pub rollouts: u32,
+    pub seed: u64,
+}
+
+impl Evaluator for RolloutEvaluator {
+    fn evaluate(&self, board: &Board, turn: Player) -> Evaluation {
+        let seed = seed::derive(self.seed, position_key(board, turn));
+        let value = average_random_rollout(board, &turn, self.rollouts, Workers::Sequential, None, seed);
+        Evaluation {
+            value: value * turn.sign(),
+            priors: normalise(board, |_| 1.),
+        }
+    }
+}
+
+/// A quick static evaluation. The value counts the lines of four each player could still
+/// complete, weighted by how many stones they already hold. Priors favour winning moves, then
+/// blocks, then central columns.
+#[derive(Clone, Debug)]
+pub struct HeuristicEvaluator;
+
+//weight of an open line holding 0, 1, 2 or 3 stones of one player
+const LINE_WEIGHTS: [f32; 4] = [0., 1., 3., 9.];
+const VALUE_SCALE: f32 = 20.;
+
+impl Evaluator for HeuristicEvaluator {
+    fn evaluate(&self, board: &Board, turn: Player) -> Evaluation {
+        let mut score = 0.;
+        for &(dx, dy) in &[(1, 0), (0, 1), (1, 1), (1, -1)] {
+            for x in 0..board.w as i32 {
+                for y in 0..board.h as i32 {
+                    let (end_x, end_y) = (x + 3 * dx, y + 3 * dy);
+                    if end_x >= board.w as i32 || end_y < 0 || end_y >= board.h as i32 {
+                        continue;
+                    }
+                    let (mut mine, mut theirs) = (0, 0);
+                    for k in 0..4 {
+                        let p = board.get((x + k * dx) as u32, (y + k * dy) as u32);
+                        if p == turn {
+                            mine += 1;
+                        } else if p != Player::Empty {
+                            theirs += 1;
+                        }
+                    }
+                    if theirs == 0 && mine < 4 {
+                        score += LINE_WEIGHTS[mine];
+                    } else if mine == 0 && theirs < 4 {
+                        score -= LINE_WEIGHTS[theirs];
+                    }
+                }
+            }
+        }
+
+        let centre = board.w as i32 - 1;
+        let priors = normalise(board, |column| {
+            if wins_at(board, turn, column) {
+                100.
+            } else if wins_at(board, turn.switch(), column) {
+                50.
+            } else {
+                //doubled distance from the centre, as in move ordering
+                1. + (board.w as i32 - (2 * column as i32 - centre).abs()) as f32 / 2.
+            }
+        });
+        Evaluation {
+            value: (score / VALUE_SCALE).tanh(),
+            priors: priors,
+        }
+    }
+}
+
+/// Another evaluator's values plus up to `noise` of uniform random error, for weaker play. The
+/// error is fixed per position by `seed`, so a position scores the same every time it is seen.
+/// Values stay short of ±1, so a guess never ties with a proven result.
+#[derive(Clone, Debug)]
+pub struct NoisyEvaluator {
+    pub inner: Arc<Evaluator>,
+    pub noise: f32,
+    pub seed: u64,
+}
+
+impl Evaluator for NoisyEvaluator {
+    fn evaluate(&self, board: &Board, turn: Player) -> Evaluation {
+        self.inner.evaluate(board, turn).with_noise(self, board, turn)
+    }
+
+    fn evaluate_batch(&self, positions: &[(&Board, Player)]) -> Vec<Evaluation> {
+        self.inner
+            .evaluate_batch(positions)
+            .into_iter()
+            .zip(positions)
+            .map(|(e, &(board, turn))| e.with_noise(self, board, turn))
+            .collect()
+    }
+}
+
+const NOISY_LIMIT: f32 = 0.99;
+
+impl Evaluation {
+    fn with_noise(mut self, noisy: &NoisyEvaluator, board: &Board, turn: Player) -> Evaluation {
+        let error: f32 = seed::rng(noisy.seed, position_key(board, turn)).gen_range(-1., 1.);
+        self.value = (self.value + noisy.noise * error).max(-NOISY_LIMIT).min(NOISY_LIMIT);
+        self
+    }
+}
+
+/// `weight` for every legal column of `board`, scaled to sum to 1, and 0 for full columns.
+pub fn normalise<F: Fn(u32) -> f32>(board: &Board, weight: F) -> Vec<f32> {
+    let mut priors: Vec<f32> = (0..board.w)
+        .map(|column| {
+            if board.get(column, board.h - 1) == Player::Empty {
+                weight(column)
+            } else {
+                0.
+            }
+        })
+        .collect();
+    let total: f32 = priors.iter().sum();
+    if total > 0. {
+        for p in priors.iter_mut() {
+            *p /= total;
+        }
+    }
+    priors
+}
+
+pub(crate) fn wins_at(board: &Board, p: Player, column: u32) -> bool {
+    match board.place(column, p) {
+        Some(mut b) => b.is_over(column),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_distribution(e: &Evaluation, board: &Board) {
+        assert_eq!(e.priors.len(), board.w as usize);
+        assert!((e.priors.iter().sum::<f32>() - 1.).abs() < 1e-5);
+        assert!(e.value >= -1. && e.value <= 1.);
+    }
+
+    #[test]
+    fn rollouts_judge_from_the_side_to_move() {
+        //P1 to move wins at once, P2 to move can block
+        let board = Board::from_int_array(vec![1, 1, 1, 0, 0, 0, 0], 7, 1);
+        let evaluator = RolloutEvaluator { rollouts: 200, seed: 1 };
+        let p1 = evaluator.evaluate(&board, Player::P1);
+        let p2 = evaluator.evaluate(&board, Player::P2);
+        assert_distribution(&p1, &board);
+        assert!(p1.value > 0.);
+        assert!(p2.value < p1.value);
+        assert_eq!(p1.priors[3], p1.priors[4]);
+    }
+
+    #[test]
+    fn heuristic_prefers_wins_blocks_and_the_centre() {
+        let empty = Board::from_int_array(vec![0; 42], 7, 6);
+        let e = HeuristicEvaluator.evaluate(&empty, Player::P1);
+        assert_distribution(&e, &empty);
+        assert_eq!(e.value, 0.);
+        assert!(e.priors[3] > e.priors[2] && e.priors[2] > e.priors[0]);
+        assert_eq!(e.priors[1], e.priors[5]);
+
+        let board = Board::from_int_array(vec![1, 1, 1, 0, -1, -1, 0], 7, 1);
+        let e = HeuristicEvaluator.evaluate(&board, Player::P1);
+        assert_distribution(&e, &board);
+        assert!(e.priors[3] > e.priors[6] && e.priors[6] > 0.);
+        assert_eq!(e.priors[0], 0.);
+        let e = HeuristicEvaluator.evaluate(&board, Player::P2);
+        assert!(e.priors[3] > e.priors[6]);
+    }
+
+    #[test]
+    fn noise_is_bounded_and_repeatable() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let noisy = NoisyEvaluator {
+            inner: Arc::new(HeuristicEvaluator),
+            noise: 0.3,
+            seed: 1,
+        };
+        let e = noisy.evaluate(&board, Player::P1);
+        assert!(e.value != 0. && e.value.abs() <= 0.3);
+        assert_eq!(e, noisy.evaluate(&board, Player::P1));
+        assert_eq!(noisy.evaluate_batch(&[(&board, Player::P1)]), vec![e.clone()]);
+        assert_eq!(e.priors, HeuristicEvaluator.evaluate(&board, Player::P1).priors);
+    }
+
+    #[test]
+    fn heuristic_values_open_lines() {
+        //P1 in the centre, P2 in a corner
+        let mut cells = vec![0; 42];
+        cells[3] = 1;
+        cells[0] = -1;
+        let board = Board::from_int_array(cells, 7, 6);
+        let p2 = HeuristicEvaluator.evaluate(&board, Player::P2);
+        let p1 = HeuristicEvaluator.evaluate(&board, Player::P1);
+        assert!(p1.value > 0.);
+        assert_eq!(p1.value, -p2.value);
+    }
+}
@@ -0,0 +1,23 @@
+use c4::*;
+
+use rand::{Rng, XorShiftRng};
+
+//positions shared by the tests of several modules
+
+/// A random game of `stones` moves that nobody has won yet, and the player to move.
+pub fn random_position(w: u32, h: u32, stones: u32, rng: &mut XorShiftRng) -> (Board, Player) {
+    'retry: loop {
+        let mut board = Board::from_int_array(vec![0; (w * h) as usize], w, h);
+        let mut turn = Player::P1;
+        for _ in 0..stones {
+            let columns: Vec<u32> = (0..w).filter(|&c| board.place(c, turn).is_some()).collect();
+            let column = columns[rng.gen_range(0, columns.len())];
+            board = board.place(column, turn).unwrap();
+            if board.is_over(column) {
+                continue 'retry;
+            }
+            turn = turn.switch();
+        }
+        return (board, turn);
+    }
+}
@@ -4,5 +4,26 @@ extern crate rayon;
 
 pub mod c4;
 pub mod mcts;
+pub mod transposition;
+pub mod ordering;
+pub mod config;
+pub mod engine;
+pub mod uct;
+pub mod rayon_tree;
+pub mod solver;
+pub mod book;
+pub mod tablebase;
+pub mod evaluator;
+pub mod network;
+pub mod training;
+pub mod ntuple;
+pub mod pns;
+pub mod cancel;
+pub mod stats;
+pub mod seed;
+pub mod difficulty;
 mod tree;
+mod bytes;
+#[cfg(test)]
+mod fixtures;
 mod rollout;
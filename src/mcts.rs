@@ -1,141 +1,743 @@
 use c4::*;
 use tree::*;
 use rollout::*;
+use transposition::*;
+use ordering::*;
+use config::*;
+use evaluator::Evaluator;
+use rayon_tree::Pool;
+use cancel::CancelToken;
+use stats::*;
+use seed;
+
+use std::time::{Duration, Instant};
+
+/// How often `search_with` reports progress while a depth is being searched.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
 
 
 pub fn mcts(board: &Board, p: Player) -> u32 {
-    let mut n = InnerNode::new(board.clone(), p);
-    n.find_children();
+    search(board, p, &SearchConfig::default())
+        .expect("default config is valid")
+        .best_move
+}
+
+/// Searches `board` for `p` as configured by `config`. At least one depth is always searched
+/// in full, whatever the time and node limits.
+///
+/// Panics if `p` has no legal move.
+pub fn search(board: &Board, p: Player, config: &SearchConfig) -> Result<SearchResult, ConfigError> {
+    config.validate()?;
+    if let Some(result) = precomputed_result(board, p, config) {
+        return Ok(result);
+    }
+    let mut table = TranspositionTable::new(config.table_size);
+    let result = iterative_deepening(board, p, config, &mut table, |_| ());
+    Ok(result.expect("no move found"))
+}
+
+/// Like `search`, but can be stopped and reports on its way. Once `cancel` is cancelled the
+/// search winds down within a few nodes and returns the deepest completed result, `None` if
+/// not even depth 1 was complete. `progress` is called after every completed depth and every
+/// `PROGRESS_INTERVAL` in between.
+pub fn search_with(
+    board: &Board,
+    p: Player,
+    config: &SearchConfig,
+    cancel: &CancelToken,
+    progress: &mut FnMut(&Progress),
+) -> Result<Option<SearchResult>, ConfigError> {
+    config.validate()?;
+    if let Some(result) = precomputed_result(board, p, config) {
+        progress(&Progress::of(&result, Duration::from_secs(0)));
+        return Ok(Some(result));
+    }
+    let mut table = TranspositionTable::new(config.table_size);
+    let mut root = InnerNode::new(board.clone(), p);
+    Ok(deepen(&mut root, config, &mut table, None, Some(cancel), Some(progress), |_| ()))
+}
+
+/// Like `search`, but runs rollouts on `pool` whatever `config.threads` says.
+pub fn search_in(pool: &Pool, board: &Board, p: Player, config: &SearchConfig) -> Result<SearchResult, ConfigError> {
+    config.validate()?;
+    if let Some(result) = precomputed_result(board, p, config) {
+        return Ok(result);
+    }
+    let mut table = TranspositionTable::new(config.table_size);
+    let mut root = InnerNode::new(board.clone(), p);
+    let result = deepen(&mut root, config, &mut table, Some(pool), None, None, |_| ());
+    Ok(result.expect("no move found"))
+}
+
+/// The game-theoretic result of a position for the player to move.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+/// The score of one move from the searched position.
+#[derive(Clone, Debug)]
+pub struct MoveScore {
+    pub column: u32,
+    pub score: f32,
+    /// Whether `score` was backed up from finished games only.
+    pub proven: bool,
+}
 
-    let mut step_data = StepData::new(p);
+/// A snapshot of a running search, for showing live analysis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Progress {
+    /// The best move of the deepest completed depth.
+    pub best_move: u32,
+    /// Its score, from P1's point of view like `SearchResult::score`.
+    pub score: f32,
+    /// The deepest completed depth.
+    pub depth: i32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    fn of(result: &SearchResult, elapsed: Duration) -> Progress {
+        Progress {
+            best_move: result.best_move,
+            score: result.score,
+            depth: result.depth,
+            nodes: result.nodes,
+            elapsed: elapsed,
+        }
+    }
+}
 
-    for child in n.children {
-        let score = mcts_step(child.node, step_data.next());
-        if step_data.update(score, child.input){ 
+/// Everything a search found out about a position. Scores are from P1's point of view: positive
+/// favours P1 and negative favours P2.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: u32,
+    pub score: f32,
+    /// Every legal move in the order it was searched, each with an exact score. Only the book
+    /// move for results from the book.
+    pub move_scores: Vec<MoveScore>,
+    /// The expected line of play, starting with `best_move`.
+    pub principal_variation: Vec<u32>,
+    pub depth: i32,
+    /// Set when the search saw far enough to prove the result for the player to move.
+    pub proven: Option<Outcome>,
+    pub nodes: u64,
+    /// Whether the answer came from `config.book` or `config.tablebase` rather than a search.
+    pub precomputed: bool,
+    /// The work done by the whole search, including any depth cut short.
+    pub stats: SearchStats,
+    /// The work done by each completed depth, from depth 1.
+    pub depth_stats: Vec<SearchStats>,
+}
+
+impl SearchResult {
+    /// How many times more nodes the last completed depth took than the one before.
+    pub fn effective_branching_factor(&self) -> Option<f32> {
+        effective_branching_factor(&self.depth_stats)
+    }
+}
+
+/// Searches depth 1, 2, 3, ... up to `config.max_depth`, trying the previous iteration's best
+/// move first. `report` is called after every completed depth. An iteration cut short by the
+/// time or node limit is thrown away, so the result is always that of the deepest completed
+/// depth; depth 1 is never cut short. Results are shared between iterations, and with later
+/// searches, through `table`.
+pub fn iterative_deepening<F>(
+    board: &Board,
+    p: Player,
+    config: &SearchConfig,
+    table: &mut TranspositionTable,
+    report: F,
+) -> Option<SearchResult>
+where
+    F: FnMut(&SearchResult),
+{
+    let mut root = InnerNode::new(board.clone(), p);
+    deepen(&mut root, config, table, None, None, None, report)
+}
+
+/// Iterative deepening from `root`, expanding the tree below it in place. The top
+/// `config.tree_plies` plies of the tree are kept for later searches. Rollouts run on `pool` if
+/// one is given, otherwise as `config.threads` says. Cancelling `cancel` ends the search as soon
+/// as possible, even during depth 1. `progress`, if given, hears about the search as
+/// `search_with` describes.
+pub(crate) fn deepen<F>(
+    root: &mut InnerNode,
+    config: &SearchConfig,
+    table: &mut TranspositionTable,
+    pool: Option<&Pool>,
+    cancel: Option<&CancelToken>,
+    progress: Option<&mut FnMut(&Progress)>,
+    mut report: F,
+) -> Option<SearchResult>
+where
+    F: FnMut(&SearchResult),
+{
+    let owned_pool = match (pool, config.threads) {
+        (None, Threads::Count(n)) => Some(Pool::new(n)),
+        _ => None,
+    };
+    let workers = match pool.or(owned_pool.as_ref()) {
+        Some(pool) => Workers::Pool(pool.thread_pool()),
+        None if config.threads == Threads::Single => Workers::Sequential,
+        None => Workers::Global,
+    };
+
+    table.new_search();
+    let mut search = Search::new(config, table, root.board.turn_number);
+    search.cancel = cancel;
+    if let Some(progress) = progress {
+        search.progress = Some(progress);
+    }
+    search.workers = workers;
+    let mut best: Option<SearchResult> = None;
+    //every depth counts towards the total, only completed ones are broken down
+    let mut total = SearchStats::default();
+    let mut depths = Vec::new();
+
+    for depth in 1..config.max_depth + 1 {
+        search.can_abort = best.is_some();
+        search.stats = SearchStats::default();
+        let depth_start = Instant::now();
+        let result = root_search(root, depth, best.as_ref(), &mut search);
+        search.stats.time = depth_start.elapsed();
+        total += &search.stats;
+        match result {
+            Some(mut result) => {
+                depths.push(search.stats.clone());
+                result.stats = total.clone();
+                result.depth_stats = depths.clone();
+                report(&result);
+                search.best = Some(Progress::of(&result, search.start.elapsed()));
+                search.report_progress();
+                best = Some(result);
+            }
+            None => break,
+        }
+        if search.out_of_budget() {
             break;
         }
     }
-    step_data.best_move.expect("no move found")
+    if let Some(ref mut best) = best {
+        best.stats = total;
+    }
+    best
+}
+
+struct Search<'a> {
+    config: &'a SearchConfig,
+    deadline: Option<Instant>,
+    root_ply: u32,
+    nodes: u64,
+    can_abort: bool,
+    aborted: bool,
+    cancel: Option<&'a CancelToken>,
+    progress: Option<&'a mut FnMut(&Progress)>,
+    //what progress reports: the deepest completed result, with the time and nodes so far
+    best: Option<Progress>,
+    //for the depth being searched
+    stats: SearchStats,
+    start: Instant,
+    last_progress: Instant,
+    workers: Workers<'a>,
+    table: &'a mut TranspositionTable,
+    heuristics: Heuristics,
+}
+
+impl<'a> Search<'a> {
+    fn new(config: &'a SearchConfig, table: &'a mut TranspositionTable, root_ply: u32) -> Search<'a> {
+        Search {
+            config: config,
+            deadline: config.time.map(|t| Instant::now() + t),
+            root_ply: root_ply,
+            nodes: 0,
+            can_abort: true,
+            aborted: false,
+            cancel: None,
+            progress: None,
+            best: None,
+            stats: SearchStats::default(),
+            start: Instant::now(),
+            last_progress: Instant::now(),
+            workers: Workers::Global,
+            table: table,
+            heuristics: Heuristics::new(),
+        }
+    }
+
+    fn out_of_budget(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+        if self.cancel.map_or(false, |c| c.is_cancelled()) {
+            self.aborted = true;
+            return true;
+        }
+        if !self.can_abort {
+            return false;
+        }
+        if let Some(limit) = self.config.nodes {
+            if self.nodes >= limit {
+                self.aborted = true;
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                self.aborted = true;
+            }
+        }
+        self.aborted
+    }
+
+    fn report_progress(&mut self) {
+        self.last_progress = Instant::now();
+        if let (Some(progress), Some(best)) = (self.progress.as_mut(), self.best.as_ref()) {
+            progress(&Progress {
+                nodes: self.nodes,
+                elapsed: self.start.elapsed(),
+                ..best.clone()
+            });
+        }
+    }
+
+    //reports progress if a while has passed since the last report
+    fn heartbeat(&mut self) {
+        if self.progress.is_some() && self.nodes % 256 == 0 && self.last_progress.elapsed() >= PROGRESS_INTERVAL {
+            self.report_progress();
+        }
+    }
+}
+
+/// Searches every move from `board` to `depth` so that every move gets an exact score, starting
+/// with the best move of the `previous` depth if given. Returns `None` if the budget ran out
+/// before the search finished.
+fn root_search(n: &mut InnerNode, depth: i32, previous: Option<&SearchResult>, search: &mut Search) -> Option<SearchResult> {
+    let p = n.turn;
+    let key = position_key(&n.board, p);
+    let hash_move = search.table.probe(key).and_then(|e| e.best_move);
+
+    if n.children.is_empty() {
+        n.find_children();
+    }
+    order_children(&search.config.ordering, n, hash_move, &search.heuristics);
+    if let Some(column) = previous.map(|r| r.best_move) {
+        if let Some(i) = n.children.iter().position(|c| c.input == column) {
+            let branch = n.children.remove(i);
+            n.children.insert(0, branch);
+        }
+    }
+
+    //scores are searched for the player to move and reported for P1
+    let sign = p.sign();
+    let mut step_data = StepData::new(depth, search.config.score_bound);
+    let mut move_scores = Vec::new();
+
+    for child in n.children.iter_mut() {
+        let guess = previous
+            .and_then(|r| r.move_scores.iter().find(|m| m.column == child.input))
+            .map(|m| sign * m.score);
+        let (score, proven) = exact_score(&mut child.node, depth, guess, search);
+        if search.aborted {
+            return None;
+        }
+        move_scores.push(MoveScore {
+            column: child.input,
+            score: sign * score,
+            proven: proven,
+        });
+        step_data.update(score, child.input);
+    }
+
+    let best_move = match step_data.best_move {
+        Some(m) => m,
+        None => return None,
+    };
+    let proven = proven_outcome(p, &move_scores);
+    search.table.store(key, Bound::Exact, depth, step_data.v, Some(best_move), proven.is_some());
+
+    Some(SearchResult {
+        best_move: best_move,
+        score: sign * step_data.v,
+        move_scores: move_scores,
+        principal_variation: principal_variation(&n.board, p, best_move, depth as usize, search.table),
+        depth: depth,
+        proven: proven,
+        nodes: search.nodes,
+        precomputed: false,
+        stats: SearchStats::default(),
+        depth_stats: Vec::new(),
+    })
 }
 
-fn mcts_step(n: Node, mut step_data: StepData) -> f32 {
-    match n {
-        Node::Leaf(leaf) => {
-            return if leaf.winner == Player::P1 {
-                1.
-            } else if leaf.winner == Player::P2 {
-                -1.
+/// The exact score, for the player to move at the root, of the root move leading to `n`, found
+/// as `search.config.algorithm` says. `guess` is its score at the previous depth.
+fn exact_score(n: &mut Node, depth: i32, guess: Option<f32>, search: &mut Search) -> (f32, bool) {
+    let bound = search.config.score_bound;
+    //root moves are not discounted
+    let mut within = |a: f32, b: f32, search: &mut Search| {
+        let mut root = StepData::new(depth, bound);
+        root.a = a;
+        root.b = b;
+        let (score, proven) = mcts_step(n, root.next(), search);
+        (-score, proven)
+    };
+
+    if search.config.algorithm == Algorithm::Mtdf {
+        //each null window search raises the lower bound or lowers the upper bound until they meet
+        let (mut lower, mut upper) = (-bound, bound);
+        let mut g = guess.unwrap_or(0.);
+        let mut result = (g, false);
+        while lower < upper {
+            let beta = if g == lower { g + NULL_WINDOW } else { g };
+            if lower > -bound || upper < bound {
+                search.stats.re_searches += 1;
+            }
+            result = within(beta - NULL_WINDOW, beta, search);
+            if search.aborted {
+                break;
+            }
+            g = result.0;
+            if g < beta {
+                upper = g;
             } else {
-                0.
-            };
+                lower = g;
+            }
         }
-        Node::InnerNode(mut node) => {
+        return result;
+    }
+
+    if let (Some(width), Some(guess)) = (search.config.aspiration, guess) {
+        let (a, b) = (guess - width, guess + width);
+        let result = within(a, b, search);
+        if search.aborted || (result.0 > a && result.0 < b) {
+            return result;
+        }
+        search.stats.re_searches += 1;
+    }
+    within(-bound, bound, search)
+}
+
+/// Returns the score of `n` for the player to move there, and whether that score was backed up
+/// from finished games only. Values in the transposition table are for the player to move too.
+fn mcts_step(n: &mut Node, mut step_data: StepData, search: &mut Search) -> (f32, bool) {
+    search.nodes += 1;
+    search.stats.nodes += 1;
+    match *n {
+        Node::Leaf(ref leaf) => {
+            search.stats.terminals += 1;
+            //a winner is always the player who just moved
+            let score = if leaf.winner == Player::Empty { 0. } else { -1. };
+            return (score, true);
+        }
+        Node::InnerNode(ref mut node) => {
+            search.heartbeat();
+            if search.out_of_budget() {
+                return (0., false);
+            }
+
+            let key = position_key(&node.board, node.turn);
+            let (a_orig, b_orig) = (step_data.a, step_data.b);
+            let mut hash_move = None;
+            search.stats.table_probes += 1;
+            if let Some(entry) = search.table.probe(key) {
+                search.stats.table_hits += 1;
+                hash_move = entry.best_move;
+                if entry.depth >= step_data.d || entry.proven {
+                    match entry.bound {
+                        Bound::Exact => {
+                            search.stats.table_cutoffs += 1;
+                            return (entry.value, entry.proven);
+                        }
+                        Bound::Lower => step_data.a = step_data.a.max(entry.value),
+                        Bound::Upper => step_data.b = step_data.b.min(entry.value),
+                    }
+                    if step_data.b <= step_data.a {
+                        search.stats.table_cutoffs += 1;
+                        return (entry.value, entry.proven);
+                    }
+                }
+            }
+
             if step_data.d == 0 {
                 //return rollout(&node.board, &node.turn);
-                return average_random_rollout(&node.board, &node.turn, 10);
+                search.stats.leaves += 1;
+                let v = match search.config.evaluator {
+                    Some(ref evaluator) => {
+                        search.stats.evaluations += 1;
+                        evaluator.evaluate(&node.board, node.turn).value
+                    }
+                    None => {
+                        search.stats.rollouts += search.config.rollouts as u64;
+                        let seed = seed::derive(search.config.seed, key);
+                        node.turn.sign() * average_random_rollout(&node.board, &node.turn, search.config.rollouts, search.workers, search.cancel, seed)
+                    }
+                };
+                //a rollout cut short by cancelling gives a meaningless mean, not to be kept
+                if search.out_of_budget() {
+                    return (0., false);
+                }
+                search.table.store(key, Bound::Exact, 0, v, None, false);
+                return (v, false);
             }
 
-            node.find_children();
-            for child in node.children {
-                let score = 0.9 * mcts_step(child.node, step_data.next());
-                if step_data.update(score, child.input){ 
+            if node.children.is_empty() {
+                node.find_children();
+            }
+            if step_data.d == 1 {
+                if let Some(ref evaluator) = search.config.evaluator {
+                    search.stats.evaluations += evaluate_leaves(&**evaluator, node, search.table);
+                }
+            }
+            order_children(&search.config.ordering, node, hash_move, &search.heuristics);
+            let discount = search.config.discount;
+            let ply = node.board.turn_number;
+            //a cutoff is proven by the move that caused it, otherwise every move must be proven
+            let mut proven = true;
+            for (i, child) in node.children.iter_mut().enumerate() {
+                let null_window = i > 0 && search.config.algorithm == Algorithm::Pvs;
+                let window = if null_window { step_data.next_null() } else { step_data.next() };
+                let (child_score, mut child_proven) = mcts_step(&mut child.node, window.discounted(discount), search);
+                let mut score = -discount * child_score;
+                if null_window && !search.aborted && score > step_data.a && score < step_data.b {
+                    //better than the moves so far, so its exact score is needed
+                    search.stats.re_searches += 1;
+                    let (s, p) = mcts_step(&mut child.node, step_data.next().discounted(discount), search);
+                    score = -discount * s;
+                    child_proven = p;
+                }
+                if search.aborted {
+                    return (0., false);
+                }
+                proven = proven && child_proven;
+                if step_data.update(score, child.input) {
+                    search.heuristics.record_cutoff(ply, child.input, step_data.d);
+                    search.stats.cutoffs += 1;
+                    if i == 0 {
+                        search.stats.first_move_cutoffs += 1;
+                    }
+                    proven = child_proven;
                     break;
                 }
             }
-            return step_data.v;
+            if ply - search.root_ply >= search.config.tree_plies {
+                node.children = Vec::new();
+            }
+
+            let bound = if step_data.v <= a_orig {
+                Bound::Upper
+            } else if step_data.v >= b_orig {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            search.table.store(key, bound, step_data.d, step_data.v, step_data.best_move, proven);
+            return (step_data.v, proven);
         }
     }
 }
 
-fn maximizing_fn(v: f32, score: f32, alpha: f32, beta: f32) -> (f32, f32, f32) {
-    return (score.max(v), alpha.max(v), beta);
+/// Evaluates every child of `node` that is not in `table` in one batch, storing the results in
+/// `table` where the children's own searches will find them. Returns how many were evaluated.
+fn evaluate_leaves(evaluator: &Evaluator, node: &InnerNode, table: &mut TranspositionTable) -> u64 {
+    let mut keys = Vec::new();
+    let mut positions = Vec::new();
+    for child in &node.children {
+        if let Node::InnerNode(ref n) = child.node {
+            let key = position_key(&n.board, n.turn);
+            if table.probe(key).is_none() {
+                keys.push(key);
+                positions.push((&n.board, n.turn));
+            }
+        }
+    }
+    if positions.is_empty() {
+        return 0;
+    }
+    let evaluations = evaluator.evaluate_batch(&positions);
+    for (key, e) in keys.into_iter().zip(evaluations) {
+        table.store(key, Bound::Exact, 0, e.value, None, false);
+    }
+    positions.len() as u64
 }
 
-fn minimizing_fn(v: f32, score: f32, alpha: f32, beta: f32) -> (f32, f32, f32) {
-    return (score.min(v), alpha, beta.min(v));
+/// The answer `config.book` or `config.tablebase` has for `board`, if any. Proven outcomes
+/// score as undiscounted wins and losses, anything else as 0.
+pub(crate) fn precomputed_result(board: &Board, p: Player, config: &SearchConfig) -> Option<SearchResult> {
+    let value = |outcome: Option<Outcome>| {
+        p.sign() * match outcome {
+            Some(Outcome::Win) => 1.,
+            Some(Outcome::Loss) => -1.,
+            _ => 0.,
+        }
+    };
+
+    if let Some(entry) = config.book.as_ref().and_then(|book| book.lookup(board, p)) {
+        return Some(SearchResult {
+            best_move: entry.column,
+            score: value(entry.outcome),
+            move_scores: vec![MoveScore {
+                column: entry.column,
+                score: value(entry.outcome),
+                proven: entry.outcome.is_some(),
+            }],
+            principal_variation: vec![entry.column],
+            depth: 0,
+            proven: entry.outcome,
+            nodes: 0,
+            precomputed: true,
+            stats: SearchStats::default(),
+            depth_stats: Vec::new(),
+        });
+    }
+
+    let tablebase = config.tablebase.as_ref()?;
+    let solution = tablebase.solve(board, p)?;
+    let move_scores = tablebase.analyse(board, p)?
+        .into_iter()
+        .map(|(column, s)| MoveScore {
+            column: column,
+            score: value(Some(s.outcome)),
+            proven: true,
+        })
+        .collect();
+    Some(SearchResult {
+        best_move: tablebase.best_move(board, p)?,
+        score: value(Some(solution.outcome)),
+        move_scores: move_scores,
+        principal_variation: tablebase.principal_variation(board, p),
+        depth: 0,
+        proven: Some(solution.outcome),
+        nodes: 0,
+        precomputed: true,
+        stats: SearchStats::default(),
+        depth_stats: Vec::new(),
+    })
+}
+
+/// The game-theoretic result for `p`, if the root move scores are enough to prove it.
+fn proven_outcome(p: Player, move_scores: &[MoveScore]) -> Option<Outcome> {
+    if move_scores.iter().any(|m| m.proven && p.sign() * m.score > 0.) {
+        return Some(Outcome::Win);
+    }
+    if move_scores.iter().any(|m| !m.proven) {
+        return None;
+    }
+    let best = move_scores
+        .iter()
+        .map(|m| p.sign() * m.score)
+        .fold(::std::f32::NEG_INFINITY, f32::max);
+    if best < 0. {
+        Some(Outcome::Loss)
+    } else {
+        Some(Outcome::Draw)
+    }
 }
 
+/// Follows best moves through `table` from `board` after `first` is played, up to `max_len` moves.
+fn principal_variation(board: &Board, p: Player, first: u32, max_len: usize, table: &TranspositionTable) -> Vec<u32> {
+    let mut pv = Vec::new();
+    let mut board = board.clone();
+    let mut turn = p;
+    let mut column = first;
+
+    while pv.len() < max_len {
+        board = match board.place(column, turn) {
+            Some(b) => b,
+            None => break,
+        };
+        pv.push(column);
+        if board.is_over(column) || board.turn_number >= board.w * board.h {
+            break;
+        }
+        turn = turn.switch();
+        column = match table.probe(position_key(&board, turn)).and_then(|e| e.best_move) {
+            Some(c) => c,
+            None => break,
+        };
+    }
+    pv
+}
+
+//the width of a null window, far below any score difference that matters
+const NULL_WINDOW: f32 = 1e-5;
+
+/// The alpha-beta window and best score so far at one node, all for the player to move there.
+/// A child's window is this one negated, as its player's gain is this player's loss.
 struct StepData {
     v: f32,
     a: f32,
     b: f32,
     d: i32,
-    compare_fn: fn(f32, f32, f32, f32) -> (f32, f32, f32),
-    p: Player,
     best_move: Option<u32>,
+    bound: f32,
 }
 
 impl StepData {
-    pub fn new(player: Player) -> StepData {
-        match player {
-            Player::P1 => {
-                return StepData {
-                    v: -2.0,
-                    a: -2.0,
-                    b: 2.0,
-                    d: 5,
-                    compare_fn: maximizing_fn,
-                    p: Player::P1,
-                    best_move: None,
-                }
-            }
-            _ => {
-                return StepData {
-                    v: 2.0,
-                    a: -2.0,
-                    b: 2.0,
-                    d: 5,
-                    compare_fn: minimizing_fn,
-                    p: Player::P2,
-                    best_move: None,
-                }
-            }
+    pub fn new(depth: i32, bound: f32) -> StepData {
+        StepData {
+            v: -bound,
+            a: -bound,
+            b: bound,
+            d: depth,
+            best_move: None,
+            bound: bound,
         }
     }
 
+    /// Records the score of `current_move` and returns whether it causes a cutoff.
     fn update(&mut self, score: f32, current_move: u32) -> bool {
-        if let None = self.best_move { self.best_move = Some(current_move)};
-        let (v_new, a_new, b_new) = (self.compare_fn)(self.v, score, self.a, self.b);
-        if self.v < v_new && self.p == Player::P1 || self.v > v_new && self.p == Player::P2 {
-            self.v = v_new;
+        if self.best_move.is_none() || score > self.v {
             self.best_move = Some(current_move);
         }
-        self.a = a_new;
-        self.b = b_new;
+        self.v = self.v.max(score);
+        self.a = self.a.max(self.v);
         if self.b <= self.a {
             self.best_move = Some(current_move);
             return true;
         }
-        return false;
+        false
+    }
+
+    /// Like `next`, but with a null window that only tells whether the child does better for
+    /// this node's player than the best move so far.
+    fn next_null(&self) -> StepData {
+        let mut next = self.next();
+        next.a = next.b - NULL_WINDOW;
+        next
+    }
+
+    /// Rescales the window for a child whose score will be multiplied by `discount`.
+    fn discounted(mut self, discount: f32) -> StepData {
+        self.a /= discount;
+        self.b /= discount;
+        self
     }
 
     fn next(&self) -> StepData {
-        match self.p {
-            Player::P1 => return StepData {
-                v: 2.0,
-                a: self.a,
-                b: self.b,
-                d: self.d - 1,
-                compare_fn: minimizing_fn,
-                p: Player::P2,
-                best_move: None,
-            },
-            _ => return StepData {
-                v: -2.0,
-                a: self.a,
-                b: self.b,
-                d: self.d - 1,
-                compare_fn: maximizing_fn,
-                p: Player::P1,
-                best_move: None,
-            },
-        };
+        StepData {
+            v: -self.bound,
+            a: -self.b,
+            b: -self.a,
+            d: self.d - 1,
+            best_move: None,
+            bound: self.bound,
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use tablebase::Tablebase;
+    use evaluator::*;
+    use network::Network;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
 
     #[test]
     fn mcts_test() {
@@ -145,6 +747,106 @@ mod test {
         assert_eq!(k, 3);
     }
 
+    #[test]
+    fn mcts_test_for_p2() {
+        let vector = vec![1, 1, 0, 0, -1, -1, 0];
+        let board = Board::from_int_array(vector,7,1);
+        let k = mcts(&board, Player::P2);
+        assert_eq!(k, 3);
+    }
+
+    //`board` flipped left to right and, if `swap`, with the colours swapped
+    fn mirrored(board: &Board, swap: bool) -> Board {
+        let mut vector = vec![0; (board.w * board.h) as usize];
+        for y in 0..board.h {
+            for x in 0..board.w {
+                let (p1, p2) = if swap { (-1, 1) } else { (1, -1) };
+                vector[(y * board.w + board.w - 1 - x) as usize] = match board.get(x, y) {
+                    Player::P1 => p1,
+                    Player::P2 => p2,
+                    Player::Empty => 0,
+                };
+            }
+        }
+        Board::from_int_array(vector, board.w, board.h)
+    }
+
+    //each position with each player to move, against its mirror image with the colours swapped
+    //and the other player to move: scores must be mirrored and negated, and the choices mirrored
+    #[test]
+    fn mirrored_positions_get_mirrored_decisions() {
+        let config = SearchConfig {
+            max_depth: 4,
+            threads: Threads::Single,
+            evaluator: Some(Arc::new(HeuristicEvaluator)),
+            ..SearchConfig::default()
+        };
+        let positions = [
+            (vec![], 7, 1),
+            (vec![0, 1, 5, 6], 7, 1),
+            (vec![], 7, 6),
+            (vec![3, 3, 2], 7, 6),
+            (vec![0, 6, 1, 6, 2], 7, 6),
+            (vec![1, 2, 2, 4, 3, 3, 5, 1], 7, 6),
+            (vec![0, 1, 1, 3, 5, 2], 6, 5),
+        ];
+        for &(ref moves, w, h) in &positions {
+            let (board, _) = Board::from_moves(w, h, moves).unwrap();
+            for &p in &[Player::P1, Player::P2] {
+                let result = search(&board, p, &config).unwrap();
+                let scores = |r: &SearchResult, flip: bool, sign: f32| {
+                    let mut scores: Vec<(u32, f32)> = r.move_scores
+                        .iter()
+                        .map(|m| (if flip { w - 1 - m.column } else { m.column }, sign * m.score))
+                        .collect();
+                    scores.sort_by_key(|&(c, _)| c);
+                    scores
+                };
+                for &swap in &[false, true] {
+                    let (q, sign) = if swap { (p.switch(), -1.) } else { (p, 1.) };
+                    let image = search(&mirrored(&board, swap), q, &config).unwrap();
+                    let message = format!("{:?} {:?} swap {}", moves, p, swap);
+                    for (a, b) in scores(&result, false, 1.).iter().zip(scores(&image, true, sign).iter()) {
+                        assert_eq!(a.0, b.0, "{}", message);
+                        assert!((a.1 - b.1).abs() < 1e-5, "{}: {:?} {:?}", message, a, b);
+                    }
+                    assert!((result.score - sign * image.score).abs() < 1e-5, "{}", message);
+                    assert_eq!(result.proven, image.proven, "{}", message);
+                    //ties may break either way, but the mirrored choice must be as good
+                    let mirrored_choice = scores(&result, false, 1.)
+                        .into_iter()
+                        .find(|&(c, _)| c == w - 1 - image.best_move)
+                        .unwrap();
+                    assert!((mirrored_choice.1 - result.score).abs() < 1e-5, "{}", message);
+                }
+            }
+        }
+    }
+
+    //the same for proven results, searched to the end of the game with rollouts at the horizon
+    #[test]
+    fn colour_swapped_positions_prove_mirrored_outcomes() {
+        let tablebase = Tablebase::build(4, 4);
+        let config = SearchConfig {
+            max_depth: 16,
+            threads: Threads::Single,
+            ..SearchConfig::default()
+        };
+        for position in tablebase.positions().filter(|p| p.moves() >= 6).step_by(1499) {
+            let turn = if position.moves() % 2 == 0 { Player::P1 } else { Player::P2 };
+            let board = position.to_board(turn);
+            let result = search(&board, turn, &config).unwrap();
+            let image = search(&mirrored(&board, true), turn.switch(), &config).unwrap();
+            assert_eq!(result.proven, image.proven);
+            assert!(result.proven.is_some());
+            assert_eq!(result.score, -image.score);
+            for m in &result.move_scores {
+                let n = image.move_scores.iter().find(|n| n.column == 3 - m.column).unwrap();
+                assert_eq!(m.score, -n.score);
+            }
+        }
+    }
+
     #[test]
     fn big_mcts_test() {
         let vector = vec![0; 49];
@@ -153,6 +855,410 @@ mod test {
         assert!(k != 0);
     }
 
+    #[test]
+    fn search_rejects_invalid_config() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let config = SearchConfig {
+            rollouts: 0,
+            ..SearchConfig::default()
+        };
+        assert_eq!(search(&board, Player::P1, &config).err(), Some(ConfigError::Rollouts(0)));
+    }
+
+    #[test]
+    fn single_threaded_search_is_deterministic() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let config = SearchConfig {
+            threads: Threads::Single,
+            ..SearchConfig::default()
+        };
+        let a = search(&board, Player::P1, &config).unwrap();
+        let b = search(&board, Player::P1, &config).unwrap();
+        let global = search(&board, Player::P1, &SearchConfig::default()).unwrap();
+
+        let scores = |r: &SearchResult| r.move_scores.iter().map(|m| (m.column, m.score)).collect::<Vec<_>>();
+        assert_eq!(scores(&a), scores(&b));
+        assert_eq!(scores(&a), scores(&global));
+        assert_eq!(a.nodes, b.nodes);
+    }
+
+    #[test]
+    fn seeds_reproduce_results_on_any_thread_count() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let scores = |seed: u64, threads: Threads| {
+            let config = SearchConfig {
+                max_depth: 3,
+                threads: threads,
+                seed: seed,
+                ..SearchConfig::default()
+            };
+            let result = search(&board, Player::P1, &config).unwrap();
+            let mut scores: Vec<(u32, f32)> = result.move_scores.iter().map(|m| (m.column, m.score)).collect();
+            scores.sort_by_key(|&(c, _)| c);
+            (result.best_move, scores)
+        };
+        let single = scores(7, Threads::Single);
+        assert_eq!(scores(7, Threads::Count(3)), single);
+        assert_eq!(scores(7, Threads::Global), single);
+        assert!(scores(8, Threads::Single).1 != single.1);
+    }
+
+    #[test]
+    fn explicit_thread_pools() {
+        let board = Board::from_int_array(vec![-1, -1, 0, 0, 1, 1, 0], 7, 1);
+        let config = SearchConfig {
+            threads: Threads::Count(2),
+            ..SearchConfig::default()
+        };
+        assert_eq!(search(&board, Player::P1, &config).unwrap().best_move, 3);
+
+        let pool = Pool::new(1);
+        let result = search_in(&pool, &board, Player::P1, &SearchConfig::default()).unwrap();
+        assert_eq!(result.best_move, 3);
+    }
+
+    #[test]
+    fn search_result_covers_every_move() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let result = search(&board, Player::P1, &SearchConfig::default()).unwrap();
+
+        let mut columns: Vec<u32> = result.move_scores.iter().map(|m| m.column).collect();
+        columns.sort();
+        assert_eq!(columns, vec![0, 1, 2, 3, 4, 5, 6]);
+
+        let best = result.move_scores.iter().find(|m| m.column == result.best_move).unwrap();
+        assert_eq!(best.score, result.score);
+        assert!(result.move_scores.iter().all(|m| m.score <= result.score));
+
+        assert_eq!(result.principal_variation[0], result.best_move);
+        assert!(result.principal_variation.len() <= 5);
+        assert_eq!(result.depth, 5);
+        assert_eq!(result.proven, None);
+        assert!(result.nodes > 0);
+    }
+
+    #[test]
+    fn search_proves_outcomes() {
+        let win = Board::from_int_array(vec![-1, -1, 0, 0, 1, 1, 0], 7, 1);
+        let result = search(&win, Player::P1, &SearchConfig::default()).unwrap();
+        assert_eq!(result.proven, Some(Outcome::Win));
+        assert_eq!(result.best_move, 3);
+        assert_eq!(result.principal_variation.len(), 3);
+
+        let loss = Board::from_int_array(vec![0, 1, 1, 1, 0], 5, 1);
+        let result = search(&loss, Player::P2, &SearchConfig::default()).unwrap();
+        assert_eq!(result.proven, Some(Outcome::Loss));
+
+        let draw = Board::from_int_array(vec![1, 0, 0, 0], 4, 1);
+        let result = search(&draw, Player::P2, &SearchConfig::default()).unwrap();
+        assert_eq!(result.proven, Some(Outcome::Draw));
+        assert_eq!(result.score, 0.);
+    }
+
+    //deep enough to see every game to the end, the search must prove the tablebase's outcome
+    #[test]
+    fn search_agrees_with_tablebase() {
+        let tablebase = Tablebase::build(4, 4);
+        let config = SearchConfig {
+            max_depth: 16,
+            threads: Threads::Single,
+            ..SearchConfig::default()
+        };
+        for position in tablebase.positions().filter(|p| p.moves() >= 8).step_by(997) {
+            let turn = if position.moves() % 2 == 0 { Player::P1 } else { Player::P2 };
+            let board = position.to_board(turn);
+            let expected = tablebase.solve(&board, turn).unwrap().outcome;
+            let result = search(&board, turn, &config).unwrap();
+            assert_eq!(result.proven, Some(expected));
+
+            let moves = tablebase.analyse(&board, turn).unwrap();
+            let chosen = moves.iter().find(|&&(c, _)| c == result.best_move).unwrap().1;
+            assert_eq!(chosen.outcome, expected);
+        }
+    }
+
+    //every variant must find the same exact root scores as plain alpha-beta
+    #[test]
+    fn algorithms_agree() {
+        let variants = [
+            (Algorithm::Pvs, None),
+            (Algorithm::Mtdf, None),
+            (Algorithm::AlphaBeta, Some(0.05)),
+            (Algorithm::Pvs, Some(0.05)),
+        ];
+        for moves in &[vec![], vec![3, 3, 2], vec![3, 2, 4, 4, 2, 3, 5]] {
+            let (board, turn) = Board::from_moves(7, 6, moves).unwrap();
+            let plain = SearchConfig {
+                max_depth: 4,
+                rollouts: 2,
+                threads: Threads::Single,
+                ..SearchConfig::default()
+            };
+            let expected = search(&board, turn, &plain).unwrap();
+            for &(algorithm, aspiration) in &variants {
+                let config = SearchConfig {
+                    algorithm: algorithm,
+                    aspiration: aspiration,
+                    ..plain.clone()
+                };
+                let result = search(&board, turn, &config).unwrap();
+                //history heuristics differ between the variants, so moves may come in another order
+                assert!((result.score - expected.score).abs() < 1e-4, "{:?} {:?}", algorithm, aspiration);
+                assert_eq!(result.move_scores.len(), expected.move_scores.len());
+                for a in &result.move_scores {
+                    let b = expected.move_scores.iter().find(|b| b.column == a.column).unwrap();
+                    assert!((a.score - b.score).abs() < 1e-4, "{:?} {:?}: {:?} {:?}", algorithm, aspiration, a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn null_window_searches_prove_outcomes() {
+        let tablebase = Tablebase::build(4, 4);
+        for &algorithm in &[Algorithm::Pvs, Algorithm::Mtdf] {
+            let config = SearchConfig {
+                max_depth: 16,
+                threads: Threads::Single,
+                algorithm: algorithm,
+                aspiration: Some(0.1),
+                ..SearchConfig::default()
+            };
+            for position in tablebase.positions().filter(|p| p.moves() >= 8).step_by(3001) {
+                let turn = if position.moves() % 2 == 0 { Player::P1 } else { Player::P2 };
+                let board = position.to_board(turn);
+                let expected = tablebase.solve(&board, turn).unwrap().outcome;
+                assert_eq!(search(&board, turn, &config).unwrap().proven, Some(expected), "{:?}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn search_collects_statistics() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let config = SearchConfig {
+            max_depth: 4,
+            threads: Threads::Single,
+            ..SearchConfig::default()
+        };
+        let result = search(&board, Player::P1, &config).unwrap();
+        let stats = &result.stats;
+        assert_eq!(result.depth_stats.len(), 4);
+        assert_eq!(result.depth_stats.iter().map(|d| d.nodes).sum::<u64>(), stats.nodes);
+        assert_eq!(stats.nodes, result.nodes);
+        assert_eq!(stats.rollouts, stats.leaves * config.rollouts as u64);
+        assert_eq!(stats.evaluations, 0);
+        assert!(stats.cutoffs > 0 && stats.first_move_cutoffs <= stats.cutoffs);
+        assert!(stats.table_hits > 0 && stats.table_hits <= stats.table_probes);
+        assert!(stats.table_cutoffs <= stats.table_hits);
+        assert!(result.depth_stats.windows(2).all(|w| w[0].nodes < w[1].nodes));
+        assert!(result.effective_branching_factor().unwrap() > 1.);
+
+        let config = SearchConfig {
+            evaluator: Some(Arc::new(HeuristicEvaluator)),
+            algorithm: Algorithm::Pvs,
+            ..config
+        };
+        let stats = search(&board, Player::P1, &config).unwrap().stats;
+        assert_eq!(stats.rollouts, 0);
+        assert!(stats.evaluations >= stats.leaves && stats.leaves > 0);
+        assert!(stats.re_searches > 0);
+    }
+
+    #[test]
+    fn cancelling_keeps_the_deepest_result() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let config = SearchConfig {
+            max_depth: 64,
+            threads: Threads::Single,
+            ..SearchConfig::default()
+        };
+        let cancel = CancelToken::new();
+        let remote = cancel.clone();
+        let timer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            remote.cancel();
+        });
+
+        let mut reports = Vec::new();
+        let result = search_with(&board, Player::P1, &config, &cancel, &mut |p| reports.push(p.clone()))
+            .unwrap()
+            .expect("depth 1 should finish in time");
+        timer.join().unwrap();
+        assert!(result.depth < 64);
+
+        //a report for every completed depth, and heartbeats in between
+        let last = reports.last().unwrap();
+        assert_eq!((last.best_move, last.depth), (result.best_move, result.depth));
+        for depth in 1..result.depth + 1 {
+            assert!(reports.iter().any(|p| p.depth == depth));
+        }
+        assert!(reports.windows(2).all(|w| w[0].depth <= w[1].depth && w[0].nodes <= w[1].nodes));
+
+        let mut called = false;
+        assert!(search_with(&board, Player::P1, &config, &cancel, &mut |_| called = true).unwrap().is_none());
+        assert!(!called);
+    }
+
+    #[test]
+    fn cancelled_rollouts_are_not_stored() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let key = position_key(&board, Player::P1);
+        let config = SearchConfig {
+            rollouts: 100000000,
+            threads: Threads::Single,
+            ..SearchConfig::default()
+        };
+        let cancel = CancelToken::new();
+        let remote = cancel.clone();
+        let timer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            remote.cancel();
+        });
+
+        let mut table = TranspositionTable::new(DEFAULT_TABLE_SIZE);
+        {
+            let mut search = Search::new(&config, &mut table, 0);
+            search.cancel = Some(&cancel);
+            search.workers = Workers::Sequential;
+            let mut leaf = Node::InnerNode(InnerNode::new(board.clone(), Player::P1));
+            assert_eq!(mcts_step(&mut leaf, StepData::new(0, config.score_bound), &mut search), (0., false));
+            assert!(search.aborted);
+        }
+        timer.join().unwrap();
+        assert!(table.probe(key).is_none());
+    }
+
+    //heuristic values, counting how the search asks for them
+    #[derive(Debug, Default)]
+    struct Counting {
+        single: AtomicUsize,
+        batches: AtomicUsize,
+        batched: AtomicUsize,
+    }
+
+    impl Evaluator for Counting {
+        fn evaluate(&self, board: &Board, turn: Player) -> Evaluation {
+            self.single.fetch_add(1, Ordering::Relaxed);
+            HeuristicEvaluator.evaluate(board, turn)
+        }
+
+        fn evaluate_batch(&self, positions: &[(&Board, Player)]) -> Vec<Evaluation> {
+            self.batches.fetch_add(1, Ordering::Relaxed);
+            self.batched.fetch_add(positions.len(), Ordering::Relaxed);
+            positions.iter().map(|&(board, turn)| HeuristicEvaluator.evaluate(board, turn)).collect()
+        }
+    }
+
+    #[test]
+    fn evaluator_scores_leaves_in_batches() {
+        let counting = Arc::new(Counting::default());
+        let config = SearchConfig {
+            max_depth: 3,
+            threads: Threads::Single,
+            evaluator: Some(counting.clone()),
+            ..SearchConfig::default()
+        };
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let result = search(&board, Player::P1, &config).unwrap();
+        assert_eq!(result.best_move, 3);
+
+        //only the first depth's leaves, directly below the root, are evaluated one by one
+        assert_eq!(counting.single.load(Ordering::Relaxed), 7);
+        assert!(counting.batches.load(Ordering::Relaxed) >= 7);
+        assert!(counting.batched.load(Ordering::Relaxed) >= 49);
+
+        let win = Board::from_int_array(vec![-1, -1, 0, 0, 1, 1, 0], 7, 1);
+        let network = Network::new(7, 1, &[8], [1, 2, 3, 4]);
+        let config = SearchConfig {
+            evaluator: Some(Arc::new(network)),
+            ..SearchConfig::default()
+        };
+        assert_eq!(search(&win, Player::P1, &config).unwrap().best_move, 3);
+    }
+
+    #[test]
+    fn iterative_deepening_reports_each_depth() {
+        let board = Board::from_int_array(vec![-1, -1, 0, 0, 1, 1, 0], 7, 1);
+        let config = SearchConfig {
+            max_depth: 4,
+            ..SearchConfig::default()
+        };
+        let mut depths = Vec::new();
+        let mut table = TranspositionTable::new(DEFAULT_TABLE_SIZE);
+        let result = iterative_deepening(&board, Player::P1, &config, &mut table, |r| {
+            depths.push(r.depth)
+        });
+
+        assert_eq!(depths, vec![1, 2, 3, 4]);
+        let result = result.expect("no depth completed");
+        assert_eq!(result.depth, 4);
+        assert_eq!(result.best_move, 3);
+    }
+
+    #[test]
+    fn iterative_deepening_stops_on_node_budget() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let config = SearchConfig {
+            max_depth: 20,
+            nodes: Some(500),
+            ..SearchConfig::default()
+        };
+        let mut last_depth = 0;
+        let mut table = TranspositionTable::new(DEFAULT_TABLE_SIZE);
+        let result = iterative_deepening(&board, Player::P1, &config, &mut table, |r| {
+            last_depth = r.depth
+        });
+
+        let result = result.expect("no depth completed");
+        assert!(result.depth < 20);
+        assert_eq!(result.depth, last_depth);
+        assert!(result.nodes <= 500);
+    }
+
+    #[test]
+    fn first_depth_ignores_budget() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let config = SearchConfig {
+            nodes: Some(0),
+            ..SearchConfig::default()
+        };
+        let mut table = TranspositionTable::new(DEFAULT_TABLE_SIZE);
+        let result = iterative_deepening(&board, Player::P1, &config, &mut table, |_| ());
+
+        assert_eq!(result.expect("no depth completed").depth, 1);
+    }
+
+    #[test]
+    fn table_saves_work_on_repeat_search() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let config = SearchConfig {
+            max_depth: 4,
+            ..SearchConfig::default()
+        };
+        let mut table = TranspositionTable::new(DEFAULT_TABLE_SIZE);
+        let first = iterative_deepening(&board, Player::P1, &config, &mut table, |_| ()).unwrap();
+        let second = iterative_deepening(&board, Player::P1, &config, &mut table, |_| ()).unwrap();
+
+        assert_eq!(first.best_move, second.best_move);
+        assert!(second.nodes < first.nodes);
+    }
+
+    #[test]
+    fn move_ordering_visits_fewer_nodes() {
+        let board = Board::from_int_array(vec![0; 49], 7, 7);
+        let plain_config = SearchConfig {
+            ordering: MoveOrdering::none(),
+            ..SearchConfig::default()
+        };
+        let mut table = TranspositionTable::new(DEFAULT_TABLE_SIZE);
+        let plain = iterative_deepening(&board, Player::P1, &plain_config, &mut table, |_| ()).unwrap();
+        let mut table = TranspositionTable::new(DEFAULT_TABLE_SIZE);
+        let ordered = iterative_deepening(&board, Player::P1, &SearchConfig::default(), &mut table, |_| ()).unwrap();
+
+        assert!(ordered.nodes < plain.nodes);
+    }
+
     //#[test]
     fn big_game() {
         let mut b = Board::from_int_array(vec![0; 49], 7, 7);
@@ -0,0 +1,315 @@
+use bytes::*;
+use c4::*;
+use evaluator::*;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: &'static [u8; 4] = b"C4NN";
+const VERSION: u8 = 1;
+
+/// A small multilayer perceptron that evaluates positions on the CPU.
+///
+/// The input is two planes of `w * h` cells, the stones of the player to move and those of the
+/// opponent. Hidden layers use ReLU. The value head is one tanh unit, the policy head has one
+/// unit per column and is turned into priors with a softmax over the legal columns.
+///
+/// `evaluate_batch` runs many positions through each layer together. Each layer still works
+/// through the positions one row at a time, so this saves little over single evaluations; it
+/// is there so a search can hand over all its leaves in one call.
+#[derive(Clone, PartialEq)]
+pub struct Network {
+    w: u32,
+    h: u32,
+    pub(crate) hidden: Vec<Dense>,
+    pub(crate) value: Dense,
+    pub(crate) policy: Dense,
+}
+
+/// A fully connected layer.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Dense {
+    pub(crate) inputs: usize,
+    pub(crate) outputs: usize,
+    //row-major, one row of `inputs` weights per output
+    pub(crate) weights: Vec<f32>,
+    pub(crate) biases: Vec<f32>,
+}
+
+impl Dense {
+    //uniform in +-sqrt(6 / inputs), which keeps ReLU activations at a steady scale
+    fn random(inputs: usize, outputs: usize, rng: &mut XorShiftRng) -> Dense {
+        let limit = (6. / inputs as f32).sqrt();
+        Dense {
+            inputs: inputs,
+            outputs: outputs,
+            weights: (0..inputs * outputs).map(|_| rng.gen_range(-limit, limit)).collect(),
+            biases: vec![0.; outputs],
+        }
+    }
+
+    /// Applies the layer to `batch` rows of `inputs` values, returning `batch` rows of outputs.
+    pub(crate) fn forward(&self, input: &[f32], batch: usize) -> Vec<f32> {
+        let mut output = Vec::with_capacity(batch * self.outputs);
+        for row in input.chunks(self.inputs).take(batch) {
+            for o in 0..self.outputs {
+                let weights = &self.weights[o * self.inputs..(o + 1) * self.inputs];
+                let sum: f32 = weights.iter().zip(row).map(|(w, x)| w * x).sum();
+                output.push(sum + self.biases[o]);
+            }
+        }
+        output
+    }
+}
+
+impl Network {
+    /// A randomly initialised network for `w` by `h` boards with the given hidden layer sizes.
+    pub fn new(w: u32, h: u32, hidden: &[usize], seed: [u32; 4]) -> Network {
+        let mut rng: XorShiftRng = SeedableRng::from_seed(seed);
+        let mut inputs = (2 * w * h) as usize;
+        let mut layers = Vec::new();
+        for &size in hidden {
+            layers.push(Dense::random(inputs, size, &mut rng));
+            inputs = size;
+        }
+        Network {
+            w: w,
+            h: h,
+            hidden: layers,
+            value: Dense::random(inputs, 1, &mut rng),
+            policy: Dense::random(inputs, w as usize, &mut rng),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.w
+    }
+
+    pub fn height(&self) -> u32 {
+        self.h
+    }
+
+    /// The input planes for `board` with `turn` to move.
+    pub fn encode(&self, board: &Board, turn: Player) -> Vec<f32> {
+        let cells = (self.w * self.h) as usize;
+        let mut input = vec![0.; 2 * cells];
+        for (i, &p) in board.vector.iter().enumerate() {
+            if p == turn {
+                input[i] = 1.;
+            } else if p != Player::Empty {
+                input[cells + i] = 1.;
+            }
+        }
+        input
+    }
+
+    /// The last hidden layer's activations for a batch of encoded positions.
+    pub(crate) fn features(&self, input: &[f32], batch: usize) -> Vec<f32> {
+        let mut x = input.to_vec();
+        for layer in &self.hidden {
+            x = layer.forward(&x, batch);
+            for v in x.iter_mut() {
+                *v = v.max(0.);
+            }
+        }
+        x
+    }
+
+    /// Value and policy logits for a batch of encoded positions.
+    pub(crate) fn heads(&self, features: &[f32], batch: usize) -> (Vec<f32>, Vec<f32>) {
+        let values = self.value.forward(features, batch).into_iter().map(|v| v.tanh()).collect();
+        (values, self.policy.forward(features, batch))
+    }
+}
+
+impl Evaluator for Network {
+    fn evaluate(&self, board: &Board, turn: Player) -> Evaluation {
+        self.evaluate_batch(&[(board, turn)]).pop().unwrap()
+    }
+
+    fn evaluate_batch(&self, positions: &[(&Board, Player)]) -> Vec<Evaluation> {
+        let batch = positions.len();
+        let mut input = Vec::with_capacity(batch * 2 * (self.w * self.h) as usize);
+        for &(board, turn) in positions {
+            assert_log("board size does not match the network", board.w == self.w && board.h == self.h);
+            input.extend(self.encode(board, turn));
+        }
+        let features = self.features(&input, batch);
+        let (values, logits) = self.heads(&features, batch);
+
+        positions
+            .iter()
+            .zip(values)
+            .zip(logits.chunks(self.w as usize))
+            .map(|((&(board, _), value), logits)| Evaluation {
+                value: value,
+                priors: softmax(board, logits),
+            })
+            .collect()
+    }
+}
+
+/// The softmax of `logits` over the legal columns of `board`, 0 for full columns.
+pub(crate) fn softmax(board: &Board, logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().cloned().fold(::std::f32::NEG_INFINITY, f32::max);
+    normalise(board, |column| (logits[column as usize] - max).exp())
+}
+
+impl Network {
+    /// Writes the weights: a header with the board size and layer shapes, then every layer's
+    /// weights and biases as little-endian `f32`s.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        //the header holds each of these in a byte
+        if self.w > 255 || self.h > 255 || self.hidden.len() > 255 {
+            return Err(invalid("network too large for the weight file format"));
+        }
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION, self.w as u8, self.h as u8, self.hidden.len() as u8])?;
+        for layer in self.layers() {
+            out.write_all(&u32_bytes(layer.outputs as u32))?;
+        }
+        for layer in self.layers() {
+            for &x in layer.weights.iter().chain(layer.biases.iter()) {
+                out.write_all(&u32_bytes(x.to_bits()))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<Network> {
+        let mut header = [0; 8];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a network weight file"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid("unsupported network weight file version"));
+        }
+        let (w, h, hidden) = (header[5] as u32, header[6] as u32, header[7] as usize);
+        if w == 0 || h == 0 {
+            return Err(invalid("network for an empty board"));
+        }
+
+        let mut sizes = vec![(2 * w * h) as usize];
+        for _ in 0..hidden + 2 {
+            sizes.push(read_u32(input)? as usize);
+        }
+        if sizes[hidden + 1] != 1 || sizes[hidden + 2] != w as usize || sizes.contains(&0) {
+            return Err(invalid("bad network layer sizes"));
+        }
+
+        let mut layers = Vec::new();
+        for i in 0..hidden + 2 {
+            //both heads read the last hidden layer
+            let inputs = sizes[i.min(hidden)];
+            let outputs = sizes[i + 1];
+            let mut read = |n: usize| -> io::Result<Vec<f32>> {
+                (0..n).map(|_| read_u32(input).map(f32::from_bits)).collect()
+            };
+            layers.push(Dense {
+                inputs: inputs,
+                outputs: outputs,
+                weights: read(inputs * outputs)?,
+                biases: read(outputs)?,
+            });
+        }
+        let policy = layers.pop().unwrap();
+        let value = layers.pop().unwrap();
+        Ok(Network {
+            w: w,
+            h: h,
+            hidden: layers,
+            value: value,
+            policy: policy,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Network> {
+        Network::read_from(&mut BufReader::new(File::open(path)?))
+    }
+
+    fn layers(&self) -> Vec<&Dense> {
+        let mut layers: Vec<&Dense> = self.hidden.iter().collect();
+        layers.push(&self.value);
+        layers.push(&self.policy);
+        layers
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hidden: Vec<usize> = self.hidden.iter().map(|l| l.outputs).collect();
+        write!(f, "Network {{ w: {}, h: {}, hidden: {:?} }}", self.w, self.h, hidden)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outputs_are_well_formed() {
+        let network = Network::new(7, 6, &[32, 16], [1, 2, 3, 4]);
+        let board = Board::from_moves(7, 6, &[3, 3, 3, 3, 3, 3, 2]).unwrap().0;
+        let e = network.evaluate(&board, Player::P2);
+        assert!(e.value > -1. && e.value < 1.);
+        assert_eq!(e.priors.len(), 7);
+        assert_eq!(e.priors[3], 0.);
+        assert!((e.priors.iter().sum::<f32>() - 1.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn batches_match_single_evaluations() {
+        let network = Network::new(7, 6, &[32], [1, 2, 3, 4]);
+        let boards: Vec<Board> = [&[][..], &[3], &[3, 2, 4], &[0, 6]]
+            .iter()
+            .map(|moves| Board::from_moves(7, 6, moves).unwrap().0)
+            .collect();
+        let turns = [Player::P1, Player::P2, Player::P2, Player::P1];
+        let positions: Vec<(&Board, Player)> = boards.iter().zip(turns.iter().cloned()).collect();
+
+        let batch = network.evaluate_batch(&positions);
+        for (e, &(board, turn)) in batch.iter().zip(positions.iter()) {
+            assert_eq!(*e, network.evaluate(board, turn));
+        }
+    }
+
+    #[test]
+    fn weight_file_round_trip() {
+        let network = Network::new(5, 4, &[12, 8], [4, 3, 2, 1]);
+        let mut bytes = Vec::new();
+        network.write_to(&mut bytes).unwrap();
+        let loaded = Network::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(loaded, network);
+
+        let board = Board::from_int_array(vec![0; 20], 5, 4);
+        assert_eq!(loaded.evaluate(&board, Player::P1), network.evaluate(&board, Player::P1));
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(Network::read_from(&mut &bytes[..]).is_err());
+        assert!(Network::read_from(&mut &b"C4BK"[..]).is_err());
+
+        let wide = Network::new(256, 1, &[1], [4, 3, 2, 1]);
+        assert!(wide.write_to(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn encodes_from_the_side_to_move() {
+        let network = Network::new(7, 6, &[8], [1, 2, 3, 4]);
+        let board = Board::from_moves(7, 6, &[3]).unwrap().0;
+        let p1 = network.encode(&board, Player::P1);
+        let p2 = network.encode(&board, Player::P2);
+        assert_eq!(p1[3], 1.);
+        assert_eq!(p2[42 + 3], 1.);
+        assert_eq!(p1.iter().sum::<f32>(), 1.);
+    }
+}
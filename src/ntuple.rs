@@ -0,0 +1,465 @@
+use bytes::*;
+use c4::*;
+use evaluator::*;
+use seed;
+use transposition::position_key;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &'static [u8; 4] = b"C4NT";
+const VERSION: u8 = 1;
+//sharpness of the softmax over move values that gives the priors
+const PRIOR_SCALE: f32 = 3.;
+//each tuple has a table of 3^length weights, over half a million at this length
+const MAX_TUPLE_LENGTH: usize = 12;
+
+/// An N-tuple network: a value function made of many lookup tables, each indexed by the
+/// contents of a fixed set of cells.
+///
+/// Each tuple is a connected group of cells, read as a base 3 number: empty, a stone of the
+/// player to move or a stone of the opponent. The value is the tanh of the sum of the table
+/// entries, and every tuple is also read reflected left to right with the same table, so the
+/// value is symmetric. Evaluation touches only one entry per tuple, which makes it fast enough
+/// to guide rollouts.
+#[derive(Clone, PartialEq)]
+pub struct NTupleNetwork {
+    w: u32,
+    h: u32,
+    tuples: Vec<Vec<u32>>,
+    mirrors: Vec<Vec<u32>>,
+    weights: Vec<Vec<f32>>,
+}
+
+/// Parameters for temporal-difference training by self-play.
+#[derive(Clone, Debug)]
+pub struct TdConfig {
+    pub games: u32,
+    /// The step size, shared out between the table entries a position reads.
+    pub learning_rate: f32,
+    /// How far back along a game each update reaches, from 0 (one step) to 1 (the final result).
+    pub lambda: f32,
+    /// The chance of playing a random move instead of the best one.
+    pub epsilon: f32,
+    pub seed: u32,
+}
+
+impl Default for TdConfig {
+    fn default() -> TdConfig {
+        TdConfig {
+            games: 10000,
+            learning_rate: 0.5,
+            lambda: 0.5,
+            epsilon: 0.1,
+            seed: 1,
+        }
+    }
+}
+
+impl NTupleNetwork {
+    /// `count` tuples of `length` cells each, grown as random walks between neighbouring cells,
+    /// with every weight 0. Panics if `length` is over 12.
+    pub fn new(w: u32, h: u32, count: usize, length: usize, seed: [u32; 4]) -> NTupleNetwork {
+        assert_log("n-tuples are at most 12 cells long", length <= MAX_TUPLE_LENGTH);
+        let mut rng: XorShiftRng = SeedableRng::from_seed(seed);
+        let length = length.min((w * h) as usize);
+        let mut tuples = Vec::new();
+        for _ in 0..count {
+            let mut cells = vec![rng.gen_range(0, w * h)];
+            while cells.len() < length {
+                //grow from any cell already in the tuple, so dead ends don't stall the walk
+                let from = cells[rng.gen_range(0, cells.len())];
+                let (x, y) = ((from % w) as i32 + rng.gen_range(-1, 2), (from / w) as i32 + rng.gen_range(-1, 2));
+                if x < 0 || y < 0 || x >= w as i32 || y >= h as i32 {
+                    continue;
+                }
+                let cell = y as u32 * w + x as u32;
+                if !cells.contains(&cell) {
+                    cells.push(cell);
+                }
+            }
+            tuples.push(cells);
+        }
+        NTupleNetwork::with_tuples(w, h, tuples)
+    }
+
+    /// The network usually used for the standard board: 70 tuples of 8 cells.
+    pub fn standard(seed: [u32; 4]) -> NTupleNetwork {
+        NTupleNetwork::new(7, 6, 70, 8, seed)
+    }
+
+    fn with_tuples(w: u32, h: u32, tuples: Vec<Vec<u32>>) -> NTupleNetwork {
+        let mirrors = tuples
+            .iter()
+            .map(|t| t.iter().map(|&c| (c / w) * w + w - 1 - c % w).collect())
+            .collect();
+        let weights = tuples.iter().map(|t| vec![0.; 3usize.pow(t.len() as u32)]).collect();
+        NTupleNetwork {
+            w: w,
+            h: h,
+            tuples: tuples,
+            mirrors: mirrors,
+            weights: weights,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.w
+    }
+
+    pub fn height(&self) -> u32 {
+        self.h
+    }
+
+    pub fn tuples(&self) -> &[Vec<u32>] {
+        &self.tuples
+    }
+
+    /// The value of `board` for `turn`, the player to move, from -1 to 1.
+    pub fn value(&self, board: &Board, turn: Player) -> f32 {
+        self.sum(board, turn).tanh()
+    }
+
+    fn sum(&self, board: &Board, turn: Player) -> f32 {
+        assert_log("board size does not match the n-tuple network", board.w == self.w && board.h == self.h);
+        self.active(board, turn).map(|(t, i)| self.weights[t][i]).sum()
+    }
+
+    //the table entry each tuple and its mirror image read, as (tuple, index) pairs
+    fn active<'a>(&'a self, board: &'a Board, turn: Player) -> Box<Iterator<Item = (usize, usize)> + 'a> {
+        let index = move |cells: &Vec<u32>| {
+            cells.iter().rev().fold(0, |index, &c| {
+                let p = board.vector[c as usize];
+                index * 3 + if p == Player::Empty { 0 } else if p == turn { 1 } else { 2 }
+            })
+        };
+        Box::new((0..self.tuples.len()).flat_map(move |t| vec![(t, index(&self.tuples[t])), (t, index(&self.mirrors[t]))]))
+    }
+
+    /// The value of playing each legal column, for the player making the move: 1 for a
+    /// winning move, 0 for a drawing one, otherwise minus the opponent's value afterwards.
+    pub fn move_values(&self, board: &Board, turn: Player) -> Vec<(u32, f32)> {
+        (0..board.w)
+            .filter_map(|column| {
+                board.place(column, turn).map(|mut next| {
+                    let value = if next.is_over(column) {
+                        1.
+                    } else if next.turn_number >= next.w * next.h {
+                        0.
+                    } else {
+                        -self.value(&next, turn.switch())
+                    };
+                    (column, value)
+                })
+            })
+            .collect()
+    }
+
+    /// The best column by `move_values`, or with probability `epsilon` a random legal one.
+    pub fn choose_move(&self, board: &Board, turn: Player, epsilon: f32, rng: &mut XorShiftRng) -> u32 {
+        let values = self.move_values(board, turn);
+        if epsilon > 0. && rng.gen_range(0., 1.) < epsilon {
+            return values[rng.gen_range(0, values.len())].0;
+        }
+        values.iter().fold(values[0], |best, &m| if m.1 > best.1 { m } else { best }).0
+    }
+
+    /// Trains by TD(λ) on games the network plays against itself, choosing moves with
+    /// `choose_move`. The value learnt is that of the positions reached, looking back from
+    /// each game's result.
+    pub fn train(&mut self, config: &TdConfig) {
+        let mut rng: XorShiftRng = SeedableRng::from_seed([config.seed, 0x5eed, 3, 5]);
+        let learning_rate = config.learning_rate / (2 * self.tuples.len()) as f32;
+        for _ in 0..config.games {
+            let mut board = Board::from_int_array(vec![0; (self.w * self.h) as usize], self.w, self.h);
+            let mut turn = Player::P1;
+            //eligibility of every entry read so far this game
+            let mut traces: HashMap<(usize, usize), f32> = HashMap::new();
+            //values and gradients are for P1 throughout, so traces need no sign flips
+            loop {
+                let v = self.value(&board, turn);
+                let value = v * turn.sign();
+                for e in traces.values_mut() {
+                    *e *= config.lambda;
+                }
+                let gradient = (1. - v * v) * turn.sign();
+                for key in self.active(&board, turn) {
+                    *traces.entry(key).or_insert(0.) += gradient;
+                }
+
+                let column = self.choose_move(&board, turn, config.epsilon, &mut rng);
+                board = board.place(column, turn).unwrap();
+                let (target, over) = if board.is_over(column) {
+                    (turn.sign(), true)
+                } else if board.turn_number >= self.w * self.h {
+                    (0., true)
+                } else {
+                    (self.value(&board, turn.switch()) * turn.switch().sign(), false)
+                };
+
+                let step = learning_rate * (target - value);
+                for (&(t, i), e) in &traces {
+                    self.weights[t][i] += step * e;
+                }
+                if over {
+                    break;
+                }
+                turn = turn.switch();
+            }
+        }
+    }
+}
+
+impl Evaluator for NTupleNetwork {
+    /// The network's value, with priors from a softmax over `move_values`.
+    fn evaluate(&self, board: &Board, turn: Player) -> Evaluation {
+        let values = self.move_values(board, turn);
+        Evaluation {
+            value: self.value(board, turn),
+            priors: normalise(board, |column| {
+                let &(_, v) = values.iter().find(|&&(c, _)| c == column).unwrap();
+                (PRIOR_SCALE * v).exp()
+            }),
+        }
+    }
+}
+
+/// Values from the average of rollouts whose moves an N-tuple network picks, with probability
+/// `epsilon` of a random move instead. Guided rollouts play far more like real games than
+/// random ones, so fewer of them give a useful value. Rollouts are seeded with `seed::rng`
+/// from `seed` and the position, so a position always gets the same value.
+#[derive(Clone, Debug)]
+pub struct GuidedRolloutEvaluator {
+    pub network: Arc<NTupleNetwork>,
+    pub rollouts: u32,
+    pub epsilon: f32,
+    pub seed: u64,
+}
+
+impl Evaluator for GuidedRolloutEvaluator {
+    fn evaluate(&self, board: &Board, turn: Player) -> Evaluation {
+        let seed = seed::derive(self.seed, position_key(board, turn));
+        let total: f32 = (0..self.rollouts)
+            .map(|i| guided_rollout(&self.network, board, turn, self.epsilon, &mut seed::rng(seed, i as u64)))
+            .sum();
+        Evaluation {
+            value: total / self.rollouts.max(1) as f32 * turn.sign(),
+            priors: self.network.evaluate(board, turn).priors,
+        }
+    }
+}
+
+/// Plays `board` out with `choose_move` for both players and scores the result like
+/// `random_rollout`: 1 if P1 wins, -1 if P2 wins, 0 for a draw.
+pub fn guided_rollout(network: &NTupleNetwork, board: &Board, turn: Player, epsilon: f32, rng: &mut XorShiftRng) -> f32 {
+    let mut board = board.clone();
+    let mut turn = turn;
+    while board.turn_number < board.w * board.h {
+        let column = network.choose_move(&board, turn, epsilon, rng);
+        board = board.place(column, turn).unwrap();
+        if board.is_over(column) {
+            return turn.sign();
+        }
+        turn = turn.switch();
+    }
+    0.
+}
+
+impl NTupleNetwork {
+    /// Writes the network: a header with the board size and tuple count, each tuple's length
+    /// and cells, then every table as little-endian `f32`s.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        out.write_all(&[VERSION, self.w as u8, self.h as u8, 0])?;
+        out.write_all(&u32_bytes(self.tuples.len() as u32))?;
+        for tuple in &self.tuples {
+            out.write_all(&[tuple.len() as u8])?;
+            out.write_all(&tuple.iter().map(|&c| c as u8).collect::<Vec<u8>>())?;
+        }
+        for table in &self.weights {
+            for &x in table {
+                out.write_all(&u32_bytes(x.to_bits()))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<NTupleNetwork> {
+        let mut header = [0; 8];
+        input.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not an n-tuple network file"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid("unsupported n-tuple network file version"));
+        }
+        let (w, h) = (header[5] as u32, header[6] as u32);
+        if w == 0 || h == 0 {
+            return Err(invalid("n-tuple network for an empty board"));
+        }
+
+        let count = read_u32(input)?;
+        let mut tuples = Vec::new();
+        for _ in 0..count {
+            let mut length = [0; 1];
+            input.read_exact(&mut length)?;
+            if length[0] as usize > MAX_TUPLE_LENGTH {
+                return Err(invalid("n-tuple too long"));
+            }
+            let mut cells = vec![0; length[0] as usize];
+            input.read_exact(&mut cells)?;
+            let repeated = cells.iter().enumerate().any(|(i, c)| cells[..i].contains(c));
+            if cells.is_empty() || repeated || cells.iter().any(|&c| c as u32 >= w * h) {
+                return Err(invalid("bad n-tuple"));
+            }
+            tuples.push(cells.into_iter().map(|c| c as u32).collect());
+        }
+
+        let mut network = NTupleNetwork::with_tuples(w, h, tuples);
+        for table in network.weights.iter_mut() {
+            for x in table.iter_mut() {
+                *x = f32::from_bits(read_u32(input)?);
+            }
+        }
+        Ok(network)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write_to(&mut out)?;
+        out.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<NTupleNetwork> {
+        NTupleNetwork::read_from(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+impl fmt::Debug for NTupleNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NTupleNetwork {{ w: {}, h: {}, tuples: {} }}", self.w, self.h, self.tuples.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //the score out of 1 of the network's greedy moves against random ones, alternating colours
+    fn score_against_random(network: &NTupleNetwork, games: u32) -> f32 {
+        let mut rng: XorShiftRng = SeedableRng::from_seed([9, 8, 7, 6]);
+        let mut score = 0.;
+        for game in 0..games {
+            let me = if game % 2 == 0 { Player::P1 } else { Player::P2 };
+            let mut board = Board::from_moves(network.w, network.h, &[]).unwrap().0;
+            let mut turn = Player::P1;
+            let winner = loop {
+                let epsilon = if turn == me { 0. } else { 1. };
+                let column = network.choose_move(&board, turn, epsilon, &mut rng);
+                board = board.place(column, turn).unwrap();
+                if board.is_over(column) {
+                    break turn;
+                }
+                if board.turn_number >= board.w * board.h {
+                    break Player::Empty;
+                }
+                turn = turn.switch();
+            };
+            score += if winner == me { 1. } else if winner == Player::Empty { 0.5 } else { 0. };
+        }
+        score / games as f32
+    }
+
+    #[test]
+    fn tuples_are_connected_cells_of_the_board() {
+        let network = NTupleNetwork::standard([1, 2, 3, 4]);
+        assert_eq!(network.tuples().len(), 70);
+        for tuple in network.tuples() {
+            assert_eq!(tuple.len(), 8);
+            for (i, &c) in tuple.iter().enumerate() {
+                assert!(c < 42);
+                assert!(!tuple[..i].contains(&c));
+                if i > 0 {
+                    let touches = tuple[..i].iter().any(|&o| {
+                        let (dx, dy) = ((c % 7) as i32 - (o % 7) as i32, (c / 7) as i32 - (o / 7) as i32);
+                        dx.abs() <= 1 && dy.abs() <= 1
+                    });
+                    assert!(touches);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn values_are_symmetric() {
+        let mut network = NTupleNetwork::new(5, 4, 20, 6, [1, 2, 3, 4]);
+        network.train(&TdConfig { games: 200, ..TdConfig::default() });
+        let board = Board::from_moves(5, 4, &[0, 1, 1, 3]).unwrap().0;
+        let mirror = Board::from_moves(5, 4, &[4, 3, 3, 1]).unwrap().0;
+        let (a, b) = (network.value(&board, Player::P1), network.value(&mirror, Player::P1));
+        assert!(a != 0.);
+        assert!((a - b).abs() < 1e-5);
+    }
+
+    #[test]
+    fn td_training_beats_random_play() {
+        let mut network = NTupleNetwork::new(5, 4, 20, 6, [1, 2, 3, 4]);
+        let before = score_against_random(&network, 100);
+        network.train(&TdConfig { games: 3000, ..TdConfig::default() });
+        let after = score_against_random(&network, 100);
+        assert!(after > before && after > 0.8, "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn evaluates_as_an_evaluator() {
+        let network = Arc::new(NTupleNetwork::new(7, 1, 10, 4, [1, 2, 3, 4]));
+        //P1 to move wins at once
+        let board = Board::from_int_array(vec![1, 1, 1, 0, -1, -1, 0], 7, 1);
+        let e = network.evaluate(&board, Player::P1);
+        assert!((e.priors.iter().sum::<f32>() - 1.).abs() < 1e-5);
+        assert_eq!(e.priors[0], 0.);
+        assert!(e.priors[3] > e.priors[6]);
+
+        let guided = GuidedRolloutEvaluator {
+            network: network,
+            rollouts: 10,
+            epsilon: 0.,
+            seed: 1,
+        };
+        assert_eq!(guided.evaluate(&board, Player::P1).value, 1.);
+
+        //random moves make the rollouts differ, but the same seed repeats them
+        let noisy = |seed| GuidedRolloutEvaluator { rollouts: 100, epsilon: 1., seed: seed, ..guided.clone() };
+        let empty = Board::from_int_array(vec![0; 7], 7, 1);
+        assert_eq!(noisy(1).evaluate(&empty, Player::P1), noisy(1).evaluate(&empty, Player::P1));
+        assert!(noisy(1).evaluate(&empty, Player::P1).value != noisy(2).evaluate(&empty, Player::P1).value);
+    }
+
+    #[test]
+    fn weight_file_round_trip() {
+        let mut network = NTupleNetwork::new(5, 4, 12, 5, [4, 3, 2, 1]);
+        network.train(&TdConfig { games: 50, ..TdConfig::default() });
+        let mut bytes = Vec::new();
+        network.write_to(&mut bytes).unwrap();
+        let loaded = NTupleNetwork::read_from(&mut &bytes[..]).unwrap();
+        assert!(loaded == network);
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(NTupleNetwork::read_from(&mut &bytes[..]).is_err());
+        assert!(NTupleNetwork::read_from(&mut &b"C4NN"[..]).is_err());
+
+        //one tuple, then its length and cells
+        let header = [&MAGIC[..], &[VERSION, 7, 6, 0, 1, 0, 0, 0]].concat();
+        let error = |tuple: &[u8]| {
+            NTupleNetwork::read_from(&mut &[&header[..], tuple].concat()[..]).unwrap_err().to_string()
+        };
+        assert_eq!(error(&[13, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]), "n-tuple too long");
+        assert_eq!(error(&[2, 5, 5]), "bad n-tuple");
+        assert_eq!(error(&[2, 5, 42]), "bad n-tuple");
+    }
+}
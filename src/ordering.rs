@@ -0,0 +1,190 @@
+use c4::*;
+use tree::*;
+
+/// Which heuristics the search uses to order moves. Enabled heuristics take priority in the
+/// order listed: winning and blocking moves, the hash move, killer moves, history scores and
+/// finally the static centre-first order. Ties keep the order the children were in, so with
+/// everything off the order is left as it was.
+#[derive(Clone, Debug)]
+pub struct MoveOrdering {
+    pub tactical: bool,
+    pub hash_move: bool,
+    pub killers: bool,
+    pub history: bool,
+    pub centre_first: bool,
+}
+
+impl MoveOrdering {
+    pub fn none() -> MoveOrdering {
+        MoveOrdering {
+            tactical: false,
+            hash_move: false,
+            killers: false,
+            history: false,
+            centre_first: false,
+        }
+    }
+}
+
+impl Default for MoveOrdering {
+    fn default() -> MoveOrdering {
+        MoveOrdering {
+            tactical: true,
+            hash_move: true,
+            killers: true,
+            history: true,
+            centre_first: true,
+        }
+    }
+}
+
+/// Killer moves and history scores gathered from beta cutoffs during a search.
+pub struct Heuristics {
+    killers: Vec<[Option<u32>; 2]>,
+    history: Vec<u64>,
+}
+
+impl Heuristics {
+    pub fn new() -> Heuristics {
+        Heuristics {
+            killers: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Records that playing `column` at game ply `ply` caused a cutoff with `depth` plies left.
+    pub fn record_cutoff(&mut self, ply: u32, column: u32, depth: i32) {
+        let ply = ply as usize;
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None, None]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(column) {
+            killers[1] = killers[0];
+            killers[0] = Some(column);
+        }
+
+        let column = column as usize;
+        if self.history.len() <= column {
+            self.history.resize(column + 1, 0);
+        }
+        let depth = depth.max(1) as u64;
+        self.history[column] += depth * depth;
+    }
+
+    fn is_killer(&self, ply: u32, column: u32) -> bool {
+        match self.killers.get(ply as usize) {
+            Some(killers) => killers[0] == Some(column) || killers[1] == Some(column),
+            None => false,
+        }
+    }
+
+    fn history(&self, column: u32) -> u64 {
+        self.history.get(column as usize).cloned().unwrap_or(0)
+    }
+}
+
+/// Sorts the children of `node`, best candidates first.
+pub fn order_children(
+    ordering: &MoveOrdering,
+    node: &mut InnerNode,
+    hash_move: Option<u32>,
+    heuristics: &Heuristics,
+) {
+    let ply = node.board.turn_number;
+    let centre = node.board.w as i32 - 1;
+    let board = &node.board;
+    let turn = node.turn;
+
+    let mut keyed: Vec<_> = node.children
+        .drain(..)
+        .map(|child| {
+            let column = child.input;
+            let wins = ordering.tactical && match child.node {
+                Node::Leaf(ref l) => l.winner == turn,
+                Node::InnerNode(_) => false,
+            };
+            let blocks = ordering.tactical && opponent_wins_at(board, turn, column);
+            let hash = ordering.hash_move && hash_move == Some(column);
+            let killer = ordering.killers && heuristics.is_killer(ply, column);
+            let history = if ordering.history {
+                heuristics.history(column)
+            } else {
+                0
+            };
+            //distance from the centre, doubled so even widths have two equal centre columns
+            let centrality = if ordering.centre_first {
+                -(2 * column as i32 - centre).abs()
+            } else {
+                0
+            };
+            ((wins, blocks, hash, killer, history, centrality), child)
+        })
+        .collect();
+
+    keyed.sort_by(|a, b| b.0.cmp(&a.0));
+    node.children = keyed.into_iter().map(|(_, child)| child).collect();
+}
+
+fn opponent_wins_at(board: &Board, turn: Player, column: u32) -> bool {
+    match board.place(column, turn.switch()) {
+        Some(mut b) => b.is_over(column),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn inputs(node: &InnerNode) -> Vec<u32> {
+        node.children.iter().map(|c| c.input).collect()
+    }
+
+    #[test]
+    fn centre_first() {
+        let mut node = InnerNode::new(Board::from_int_array(vec![0; 49], 7, 7), Player::P1);
+        node.find_children();
+
+        let ordering = MoveOrdering {
+            centre_first: true,
+            ..MoveOrdering::none()
+        };
+        order_children(&ordering, &mut node, None, &Heuristics::new());
+        assert_eq!(inputs(&node), vec![3, 2, 4, 1, 5, 0, 6]);
+
+        order_children(&MoveOrdering::none(), &mut node, None, &Heuristics::new());
+        assert_eq!(inputs(&node), vec![3, 2, 4, 1, 5, 0, 6]);
+    }
+
+    #[test]
+    fn wins_then_blocks_then_hash_move() {
+        let board = Board::from_int_array(vec![1, 1, 1, 0, 0, 0, 0, -1, -1, -1], 10, 1);
+        let mut node = InnerNode::new(board, Player::P1);
+        node.find_children();
+
+        order_children(&MoveOrdering::default(), &mut node, Some(5), &Heuristics::new());
+        assert_eq!(inputs(&node)[0], 3);
+        assert_eq!(inputs(&node)[1], 6);
+        assert_eq!(inputs(&node)[2], 5);
+    }
+
+    #[test]
+    fn killers_and_history() {
+        let mut node = InnerNode::new(Board::from_int_array(vec![0; 49], 7, 7), Player::P1);
+        node.find_children();
+
+        let mut heuristics = Heuristics::new();
+        heuristics.record_cutoff(0, 6, 1);
+        heuristics.record_cutoff(5, 1, 3);
+
+        let ordering = MoveOrdering {
+            killers: true,
+            history: true,
+            ..MoveOrdering::none()
+        };
+        order_children(&ordering, &mut node, None, &heuristics);
+        assert_eq!(inputs(&node)[0], 6);
+        assert_eq!(inputs(&node)[1], 1);
+    }
+}
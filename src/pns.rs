@@ -0,0 +1,351 @@
+use c4::*;
+use mcts::Outcome;
+use tree::{InnerNode, Node};
+
+const INFINITY: u32 = ::std::u32::MAX;
+
+/// What a proof-number search tries to prove for the player to move at the root.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Goal {
+    Win,
+    /// A win or a draw.
+    NotLose,
+}
+
+/// A winning strategy: the attacker's move and, unless it ends the game, a strategy against
+/// every defence. Each defence is listed with exactly one reply of the attacker, or none if the
+/// defence ends the game in a draw.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProofTree {
+    pub column: u32,
+    pub replies: Vec<ProofTree>,
+}
+
+/// The answer of `ProofSearch::solve`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProofResult {
+    /// The value for the player to move, `None` if the node limit was reached first.
+    pub outcome: Option<Outcome>,
+    /// A move that achieves a win or a draw. `None` for a lost position, where any move
+    /// loses, and for unknown ones.
+    pub best_move: Option<u32>,
+}
+
+//a position in the search tree, reached from its parent by playing `column`
+struct PnNode {
+    column: u32,
+    proof: u32,
+    disproof: u32,
+    children: Vec<usize>,
+    expanded: bool,
+}
+
+/// Best-first proof-number search. Each step expands the most-proving leaf, the one whose
+/// result would go furthest towards settling the root, so forced wins through narrow lines are
+/// found much sooner than by a full-width search.
+///
+/// The tree is kept in memory and the search gives up once it holds `max_nodes` positions.
+/// Transpositions are not merged, so it suits tactical positions more than quiet ones.
+pub struct ProofSearch {
+    max_nodes: usize,
+    nodes: Vec<PnNode>,
+    attacker: Player,
+    goal: Goal,
+    created: u64,
+}
+
+impl ProofSearch {
+    pub fn new(max_nodes: usize) -> ProofSearch {
+        ProofSearch {
+            max_nodes: max_nodes,
+            nodes: Vec::new(),
+            attacker: Player::P1,
+            goal: Goal::Win,
+            created: 0,
+        }
+    }
+
+    /// Positions created since the search was made.
+    pub fn nodes(&self) -> u64 {
+        self.created
+    }
+
+    /// Tries to prove `goal` for `turn`, the player to move: `Some(true)` if it is reached
+    /// whatever the opponent does, `Some(false)` if the opponent can prevent it and `None` if
+    /// the node limit ran out first. The game must not be over already.
+    pub fn prove(&mut self, board: &Board, turn: Player, goal: Goal) -> Option<bool> {
+        self.nodes.clear();
+        self.attacker = turn;
+        self.goal = goal;
+        self.add(0, 1, 1);
+        while self.nodes[0].proof != 0 && self.nodes[0].disproof != 0 {
+            if self.nodes.len() >= self.max_nodes {
+                return None;
+            }
+            self.descend(0, board, turn);
+        }
+        Some(self.nodes[0].proof == 0)
+    }
+
+    /// Finds whether `board` is a win, draw or loss for `turn` with up to two proofs: first
+    /// of a win, then of at least a draw.
+    pub fn solve(&mut self, board: &Board, turn: Player) -> ProofResult {
+        let (outcome, best_move) = match self.prove(board, turn, Goal::Win) {
+            Some(true) => (Some(Outcome::Win), self.proving_move()),
+            Some(false) => match self.prove(board, turn, Goal::NotLose) {
+                Some(true) => (Some(Outcome::Draw), self.proving_move()),
+                Some(false) => (Some(Outcome::Loss), None),
+                None => (None, None),
+            },
+            None => (None, None),
+        };
+        ProofResult {
+            outcome: outcome,
+            best_move: best_move,
+        }
+    }
+
+    /// The value of every legal move for `turn`, in column order. `None` where the node limit
+    /// ran out.
+    pub fn analyse(&mut self, board: &Board, turn: Player) -> Vec<(u32, Option<Outcome>)> {
+        let mut outcomes = Vec::new();
+        for column in 0..board.w {
+            if let Some(mut next) = board.place(column, turn) {
+                let outcome = if next.is_over(column) {
+                    Some(Outcome::Win)
+                } else if next.turn_number >= next.w * next.h {
+                    Some(Outcome::Draw)
+                } else {
+                    self.solve(&next, turn.switch()).outcome.map(|o| match o {
+                        Outcome::Win => Outcome::Loss,
+                        Outcome::Loss => Outcome::Win,
+                        Outcome::Draw => Outcome::Draw,
+                    })
+                };
+                outcomes.push((column, outcome));
+            }
+        }
+        outcomes
+    }
+
+    /// The strategy found by the last `prove` or `solve`, if its final proof succeeded.
+    pub fn proof_tree(&self) -> Option<ProofTree> {
+        self.proving_move()?;
+        Some(self.strategy(self.proving_child(0)))
+    }
+
+    fn proving_move(&self) -> Option<u32> {
+        if self.nodes.is_empty() || self.nodes[0].proof != 0 {
+            return None;
+        }
+        Some(self.nodes[self.proving_child(0)].column)
+    }
+
+    fn proving_child(&self, index: usize) -> usize {
+        *self.nodes[index].children.iter().find(|&&c| self.nodes[c].proof == 0).unwrap()
+    }
+
+    //the strategy from the attacker's move that reached `index`
+    fn strategy(&self, index: usize) -> ProofTree {
+        ProofTree {
+            column: self.nodes[index].column,
+            replies: self.nodes[index]
+                .children
+                .iter()
+                .map(|&defence| ProofTree {
+                    column: self.nodes[defence].column,
+                    //a finished game is a leaf, with nothing to answer
+                    replies: if self.nodes[defence].children.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![self.strategy(self.proving_child(defence))]
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    fn add(&mut self, column: u32, proof: u32, disproof: u32) -> usize {
+        self.nodes.push(PnNode {
+            column: column,
+            proof: proof,
+            disproof: disproof,
+            children: Vec::new(),
+            expanded: false,
+        });
+        self.created += 1;
+        self.nodes.len() - 1
+    }
+
+    //walks down to the most-proving leaf, expands it and updates the numbers on the way back
+    fn descend(&mut self, index: usize, board: &Board, turn: Player) {
+        let attacking = turn == self.attacker;
+        if !self.nodes[index].expanded {
+            self.expand(index, board, turn);
+        } else {
+            let target = if attacking { self.nodes[index].proof } else { self.nodes[index].disproof };
+            let child = *self.nodes[index]
+                .children
+                .iter()
+                .find(|&&c| if attacking { self.nodes[c].proof == target } else { self.nodes[c].disproof == target })
+                .unwrap();
+            let column = self.nodes[child].column;
+            self.descend(child, &board.place(column, turn).unwrap(), turn.switch());
+        }
+        self.update(index, attacking);
+    }
+
+    fn expand(&mut self, index: usize, board: &Board, turn: Player) {
+        let mut node = InnerNode::new(board.clone(), turn);
+        node.find_children();
+        for branch in node.children {
+            let (proof, disproof) = match branch.node {
+                Node::Leaf(ref leaf) => {
+                    let achieved = leaf.winner == self.attacker || (leaf.winner == Player::Empty && self.goal == Goal::NotLose);
+                    if achieved { (0, INFINITY) } else { (INFINITY, 0) }
+                }
+                Node::InnerNode(_) => (1, 1),
+            };
+            let child = self.add(branch.input, proof, disproof);
+            self.nodes[index].children.push(child);
+        }
+        self.nodes[index].expanded = true;
+    }
+
+    //the attacker needs one proven move, the defender needs one refutation
+    fn update(&mut self, index: usize, attacking: bool) {
+        let (mut least, mut sum) = (INFINITY, 0u32);
+        for &c in &self.nodes[index].children {
+            let (proof, disproof) = (self.nodes[c].proof, self.nodes[c].disproof);
+            if attacking {
+                least = least.min(proof);
+                sum = sum.saturating_add(disproof);
+            } else {
+                least = least.min(disproof);
+                sum = sum.saturating_add(proof);
+            }
+        }
+        let node = &mut self.nodes[index];
+        if attacking {
+            node.proof = least;
+            node.disproof = sum;
+        } else {
+            node.proof = sum;
+            node.disproof = least;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tablebase::Tablebase;
+
+    //plays out `tree` against every defence and checks that each line reaches `goal`
+    fn check_strategy(tree: &ProofTree, board: &Board, attacker: Player, goal: Goal) {
+        let mut next = board.place(tree.column, attacker).unwrap();
+        let full = |b: &Board| b.turn_number >= b.w * b.h;
+        if next.is_over(tree.column) || full(&next) {
+            assert!(next.is_over(tree.column) || goal == Goal::NotLose);
+            assert!(tree.replies.is_empty());
+            return;
+        }
+        let defences: Vec<u32> = (0..board.w).filter(|&c| next.place(c, attacker.switch()).is_some()).collect();
+        assert!(!defences.is_empty());
+        assert_eq!(tree.replies.iter().map(|r| r.column).collect::<Vec<u32>>(), defences);
+        for defence in &tree.replies {
+            let mut after = next.place(defence.column, attacker.switch()).unwrap();
+            assert!(!after.is_over(defence.column));
+            if full(&after) {
+                assert_eq!(goal, Goal::NotLose);
+                assert!(defence.replies.is_empty());
+                continue;
+            }
+            assert_eq!(defence.replies.len(), 1);
+            check_strategy(&defence.replies[0], &after, attacker, goal);
+        }
+    }
+
+    #[test]
+    fn agrees_with_tablebase() {
+        let tablebase = Tablebase::build(4, 4);
+        let mut search = ProofSearch::new(1000000);
+        for position in tablebase.positions().step_by(997) {
+            let turn = if position.moves() % 2 == 0 { Player::P1 } else { Player::P2 };
+            let board = position.to_board(turn);
+            let expected = tablebase.solve(&board, turn).unwrap().outcome;
+            let result = search.solve(&board, turn);
+            assert_eq!(result.outcome, Some(expected));
+
+            match result.best_move {
+                Some(column) => {
+                    let mut next = board.place(column, turn).unwrap();
+                    if !next.is_over(column) && next.turn_number < 16 {
+                        let reply = tablebase.solve(&next, turn.switch()).unwrap().outcome;
+                        let kept = if expected == Outcome::Win { Outcome::Loss } else { Outcome::Draw };
+                        assert_eq!(reply, kept);
+                    }
+                }
+                None => assert_eq!(expected, Outcome::Loss),
+            }
+        }
+    }
+
+    #[test]
+    fn proof_trees_are_winning_strategies() {
+        //P1 has three on the bottom row with both ends open
+        let (board, turn) = Board::from_moves(7, 6, &[2, 2, 3, 3]).unwrap();
+        let mut search = ProofSearch::new(100000);
+        assert_eq!(search.prove(&board, turn, Goal::Win), Some(true));
+        let tree = search.proof_tree().unwrap();
+        check_strategy(&tree, &board, turn, Goal::Win);
+
+        //a deeper win that needs defences answered
+        let (board, turn) = Board::from_moves(7, 6, &[3, 3, 2, 2]).unwrap();
+        assert_eq!(search.solve(&board, turn).outcome, Some(Outcome::Win));
+        check_strategy(&search.proof_tree().unwrap(), &board, turn, Goal::Win);
+    }
+
+    #[test]
+    fn proof_trees_hold_draws() {
+        //the last defence fills the board
+        let (board, turn) = Board::from_moves(2, 1, &[]).unwrap();
+        let mut search = ProofSearch::new(1000);
+        assert_eq!(search.solve(&board, turn), ProofResult { outcome: Some(Outcome::Draw), best_move: Some(0) });
+        let tree = search.proof_tree().unwrap();
+        assert_eq!(tree, ProofTree { column: 0, replies: vec![ProofTree { column: 1, replies: Vec::new() }] });
+        check_strategy(&tree, &board, turn, Goal::NotLose);
+
+        let tablebase = Tablebase::build(4, 4);
+        let mut drawn = 0;
+        for position in tablebase.positions().filter(|p| p.moves() >= 8).step_by(101) {
+            let turn = if position.moves() % 2 == 0 { Player::P1 } else { Player::P2 };
+            let board = position.to_board(turn);
+            if tablebase.solve(&board, turn).unwrap().outcome == Outcome::Draw {
+                assert_eq!(search.solve(&board, turn).outcome, Some(Outcome::Draw));
+                check_strategy(&search.proof_tree().unwrap(), &board, turn, Goal::NotLose);
+                drawn += 1;
+            }
+        }
+        assert!(drawn > 0);
+    }
+
+    #[test]
+    fn analyses_every_move() {
+        let (board, turn) = Board::from_moves(7, 6, &[3, 3, 2, 2]).unwrap();
+        let mut search = ProofSearch::new(100000);
+        let moves = search.analyse(&board, turn);
+        assert_eq!(moves.len(), 7);
+        assert_eq!(moves[1], (1, Some(Outcome::Win)));
+        assert_eq!(moves[4], (4, Some(Outcome::Win)));
+    }
+
+    #[test]
+    fn gives_up_at_the_node_limit() {
+        let (board, turn) = Board::from_moves(7, 6, &[]).unwrap();
+        let mut search = ProofSearch::new(1000);
+        assert_eq!(search.solve(&board, turn), ProofResult { outcome: None, best_move: None });
+        assert!(search.proof_tree().is_none());
+        assert!(search.nodes() >= 1000);
+    }
+}
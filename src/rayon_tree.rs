@@ -1,9 +1,161 @@
-extern crate rayon;
+use c4::*;
+use uct::*;
+use mcts::Outcome;
+use seed::{self, DEFAULT_SEED};
 
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use rayon::prelude::*;
+use std::time::{Duration, Instant};
 
-struct Pool{
+/// How long each worker's tree grows for.
+#[derive(Copy, Clone, Debug)]
+pub enum Limit {
+    Playouts(u32),
+    Time(Duration),
 }
 
-impl Pool{
+/// The merged root statistics of a root-parallel search.
+#[derive(Clone, Debug)]
+pub struct ParallelResult {
+    pub best_move: u32,
+    pub stats: Vec<EdgeStats>,
+    pub playouts: u64,
+    /// The result for `p`, if any worker proved it.
+    pub proven: Option<Outcome>,
+}
+
+/// Root-parallel Monte Carlo tree search on a dedicated rayon pool.
+///
+/// Every worker grows its own `UctTree` from the same position with its own random stream,
+/// and the root visit counts are summed at the end. The trees share nothing while they run,
+/// so throughput grows with the number of threads.
+pub struct Pool {
+    pool: ThreadPool,
+}
+
+impl Pool {
+    /// A pool with `threads` workers, or one per core if `threads` is 0.
+    pub fn new(threads: usize) -> Pool {
+        Pool {
+            pool: ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("failed to build thread pool"),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    pub fn thread_pool(&self) -> &ThreadPool {
+        &self.pool
+    }
+
+    /// Searches `board` for `p` with one tree per worker. Panics if `p` has no legal move.
+    pub fn search(&self, board: &Board, p: Player, limit: Limit) -> ParallelResult {
+        self.search_seeded(board, p, limit, DEFAULT_SEED)
+    }
+
+    /// Like `search`, with worker `i`'s tree drawing from stream `i` of `seed`. With a playout
+    /// limit the result depends only on the seed and the number of threads.
+    pub fn search_seeded(&self, board: &Board, p: Player, limit: Limit, seed: u64) -> ParallelResult {
+        let threads = self.threads() as u32;
+        let deadline = match limit {
+            Limit::Time(t) => Some(Instant::now() + t),
+            Limit::Playouts(_) => None,
+        };
+
+        let trees: Vec<(Vec<EdgeStats>, u64)> = self.pool.install(|| {
+            (0..threads)
+                .into_par_iter()
+                .map(|i| {
+                    let mut tree = UctTree::new(board, p, seed::words(seed, i as u64));
+                    match limit {
+                        Limit::Playouts(n) => tree.run(n),
+                        Limit::Time(_) => tree.run_until(deadline.unwrap()),
+                    }
+                    (tree.root_stats(), tree.playouts())
+                })
+                .collect()
+        });
+
+        let mut stats: Vec<EdgeStats> = Vec::new();
+        let mut playouts = 0;
+        for (tree_stats, tree_playouts) in trees {
+            playouts += tree_playouts;
+            for s in tree_stats {
+                match stats.iter_mut().find(|m| m.column == s.column) {
+                    Some(m) => {
+                        let visits = m.visits + s.visits;
+                        if visits > 0 {
+                            m.value = (m.value * m.visits as f32 + s.value * s.visits as f32) / visits as f32;
+                        }
+                        m.visits = visits;
+                        m.proven = m.proven.or(s.proven);
+                    }
+                    None => stats.push(s),
+                }
+            }
+        }
+
+        ParallelResult {
+            best_move: pick_move(&stats).expect("no move found"),
+            proven: proven_outcome(&stats),
+            stats: stats,
+            playouts: playouts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merges_every_worker() {
+        let pool = Pool::new(3);
+        assert_eq!(pool.threads(), 3);
+
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let result = pool.search(&board, Player::P1, Limit::Playouts(300));
+
+        assert_eq!(result.playouts, 900);
+        let visits: u32 = result.stats.iter().map(|s| s.visits).sum();
+        assert_eq!(visits, 3 * 299);
+    }
+
+    #[test]
+    fn seeds_reproduce_results() {
+        let pool = Pool::new(3);
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let visits = |seed| {
+            let result = pool.search_seeded(&board, Player::P1, Limit::Playouts(200), seed);
+            result.stats.iter().map(|s| (s.column, s.visits)).collect::<Vec<_>>()
+        };
+        assert_eq!(visits(5), visits(5));
+        assert!(visits(5) != visits(6));
+    }
+
+    #[test]
+    fn merges_proofs() {
+        let pool = Pool::new(2);
+        let board = Board::from_int_array(vec![-1, -1, 0, 0, 1, 1, 0], 7, 1);
+        let result = pool.search(&board, Player::P1, Limit::Playouts(300));
+
+        assert_eq!(result.best_move, 3);
+        assert_eq!(result.proven, Some(Outcome::Win));
+    }
+
+    #[test]
+    fn time_limit() {
+        let pool = Pool::new(2);
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let start = Instant::now();
+        let result = pool.search(&board, Player::P1, Limit::Time(Duration::from_millis(100)));
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(result.playouts > 0);
+        assert!(result.best_move < 7);
+    }
 }
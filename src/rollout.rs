@@ -1,35 +1,57 @@
 extern crate rand;
 
 use c4::*;
+use cancel::CancelToken;
+use seed;
 
-use rand::{Rng, SeedableRng, XorShiftRng};
+use rand::Rng;
+use rayon::ThreadPool;
 use rayon::prelude::*;
 
 
-pub fn average_random_rollout(board_orig: &Board, p_orig: &Player, n: u32) -> f32 {
-    //let mut rng: XorShiftRng = SeedableRng::from_seed([1,2,3,4]);
-    //let mut f = move |x| rng.gen_range(0, x);
+/// Where the rollouts of one `average_random_rollout` call run.
+#[derive(Copy, Clone)]
+pub enum Workers<'a> {
+    Sequential,
+    Global,
+    Pool(&'a ThreadPool),
+}
+
+/// The mean result of `n` random rollouts, 1 for P1 wins and -1 for P2 wins. Rollout `i` draws
+/// from stream `i` of `seed`, so the result depends on nothing else, wherever the rollouts run.
+/// Once `cancel` is cancelled the remaining rollouts are skipped and the mean is meaningless.
+pub fn average_random_rollout(board_orig: &Board, p_orig: &Player, n: u32, workers: Workers, cancel: Option<&CancelToken>, seed: u64) -> f32 {
     let f = move |y| {
-        let mut rng: XorShiftRng =
-            SeedableRng::from_seed([y + 1, y ^ 2, y * 3, (y + 2) * (8 + y ^ 2)]);
+        let mut rng = seed::rng(seed, y as u64);
         move |x| rng.gen_range(0, x)
     };
-    let cumulative: f32;
-
-    /*
-    for _ in 0..n {
-        cumulative += random_rollout(board_orig, p_orig, &mut f);
-    }
-    */
+    let rollout = |x| {
+        if cancel.map_or(false, |c| c.is_cancelled()) {
+            return 0.;
+        }
+        random_rollout(board_orig, p_orig, &mut f(x))
+    };
 
-    cumulative = (0..n)
-        .into_par_iter()
-        .map(|x| random_rollout(board_orig, p_orig, &mut f(x)))
-        .sum();
+    //each rollout scores -1, 0 or 1, so the sum is exact in any order
+    let cumulative: f32 = match workers {
+        Workers::Sequential => (0..n).map(rollout).sum(),
+        Workers::Global => (0..n).into_par_iter().map(rollout).sum(),
+        Workers::Pool(pool) => pool.install(|| (0..n).into_par_iter().map(rollout).sum()),
+    };
     cumulative / (n as f32)
 }
 
-fn random_rollout(board_orig: &Board, p_orig: &Player, rng: &mut FnMut(u32) -> u32) -> f32 {
+pub fn random_rollout(board_orig: &Board, p_orig: &Player, rng: &mut FnMut(u32) -> u32) -> f32 {
+    rollout(board_orig, p_orig, rng, None)
+}
+
+/// Like `random_rollout`, but also appends every column played to `moves`, in order, for
+/// all-moves-as-first statistics.
+pub fn recorded_rollout(board_orig: &Board, p_orig: &Player, rng: &mut FnMut(u32) -> u32, moves: &mut Vec<u32>) -> f32 {
+    rollout(board_orig, p_orig, rng, Some(moves))
+}
+
+fn rollout(board_orig: &Board, p_orig: &Player, rng: &mut FnMut(u32) -> u32, mut moves: Option<&mut Vec<u32>>) -> f32 {
     let mut board = board_orig.clone();
     let mut p = p_orig.clone();
 
@@ -50,6 +72,9 @@ fn random_rollout(board_orig: &Board, p_orig: &Player, rng: &mut FnMut(u32) -> u
             }
             Some(b) => {
                 board = b;
+                if let Some(ref mut moves) = moves {
+                    moves.push(i);
+                }
 
                 if board.is_over(i) {
                     break;
@@ -64,9 +89,5 @@ fn random_rollout(board_orig: &Board, p_orig: &Player, rng: &mut FnMut(u32) -> u
         }
     }
 
-    match p {
-        Player::P1 => 1.,
-        Player::P2 => -1.,
-        Player::Empty => 0.,
-    }
+    p.sign()
 }
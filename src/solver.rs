@@ -0,0 +1,727 @@
+use c4::*;
+use mcts::Outcome;
+use seed;
+
+/// A position as a pair of bitboards, for the solver. Each column takes `h + 1` bits, bottom
+/// cell first, so the board must satisfy `w * (h + 1) <= 64`. The extra bit on top of every
+/// column is always clear, which keeps line checks from wrapping into the next column.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    w: u32,
+    h: u32,
+    current: u64,
+    mask: u64,
+    moves: u32,
+    bottom: u64,
+    board: u64,
+}
+
+impl Position {
+    pub fn fits(w: u32, h: u32) -> bool {
+        w > 0 && h > 0 && w * (h + 1) <= 64
+    }
+
+    pub fn empty(w: u32, h: u32) -> Position {
+        assert_log("board too large for a bitboard position", Position::fits(w, h));
+        let bottom = (0..w).fold(0, |m, c| m | 1 << (c * (h + 1)));
+        Position {
+            w: w,
+            h: h,
+            current: 0,
+            mask: 0,
+            moves: 0,
+            bottom: bottom,
+            board: bottom * ((1 << h) - 1),
+        }
+    }
+
+    /// The position on `board` with `turn` to move.
+    pub fn from_board(board: &Board, turn: Player) -> Position {
+        let mut position = Position::empty(board.w, board.h);
+        for column in 0..board.w {
+            for row in 0..board.h {
+                let p = board.get(column, row);
+                if p == Player::Empty {
+                    continue;
+                }
+                let bit = 1 << position.bit(column, row);
+                position.mask |= bit;
+                if p == turn {
+                    position.current |= bit;
+                }
+                position.moves += 1;
+            }
+        }
+        position
+    }
+
+    /// The equivalent `Board`, along with the player to move.
+    pub fn to_board(&self, turn: Player) -> Board {
+        let mut cells = vec![0; (self.w * self.h) as usize];
+        let mine = if turn == Player::P1 { 1 } else { -1 };
+        for column in 0..self.w {
+            for row in 0..self.h {
+                let bit = 1 << self.bit(column, row);
+                if self.current & bit != 0 {
+                    cells[(row * self.w + column) as usize] = mine;
+                } else if self.mask & bit != 0 {
+                    cells[(row * self.w + column) as usize] = -mine;
+                }
+            }
+        }
+        Board::from_int_array(cells, self.w, self.h)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.w
+    }
+
+    pub fn height(&self) -> u32 {
+        self.h
+    }
+
+    pub fn moves(&self) -> u32 {
+        self.moves
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.moves >= self.w * self.h
+    }
+
+    /// A key that is unique to this position among positions of the same size.
+    pub fn key(&self) -> u64 {
+        self.current + self.mask
+    }
+
+    /// The position with the given `key` on a `w` by `h` board.
+    pub fn from_key(w: u32, h: u32, key: u64) -> Position {
+        let mut position = Position::empty(w, h);
+        for column in 0..w {
+            //a column with n stones holds its stones plus 2^n - 1, which is below 2^(n + 1) - 1
+            let shift = position.bit(column, 0);
+            let bits = (key >> shift) & ((1 << (h + 1)) - 1);
+            let stones = 63 - (bits + 1).leading_zeros();
+            let filled = (1 << stones) - 1;
+            position.mask |= filled << shift;
+            position.current |= (bits - filled) << shift;
+            position.moves += stones;
+        }
+        position
+    }
+
+    /// The key of the left-right mirror image of this position.
+    pub fn mirror_key(&self) -> u64 {
+        self.mirror().key()
+    }
+
+    pub fn mirror(&self) -> Position {
+        let column_bits = self.h + 1;
+        let column_mask = (1 << column_bits) - 1;
+        let mut mirrored = *self;
+        mirrored.current = 0;
+        mirrored.mask = 0;
+        for column in 0..self.w {
+            let from = column * column_bits;
+            let to = (self.w - 1 - column) * column_bits;
+            mirrored.current |= ((self.current >> from) & column_mask) << to;
+            mirrored.mask |= ((self.mask >> from) & column_mask) << to;
+        }
+        mirrored
+    }
+
+    pub fn can_play(&self, column: u32) -> bool {
+        self.mask & self.top_mask(column) == 0
+    }
+
+    /// Plays `column` for the player to move, who then becomes the opponent.
+    pub fn play(&mut self, column: u32) {
+        self.current ^= self.mask;
+        self.mask |= self.mask + self.bottom_mask_col(column);
+        self.moves += 1;
+    }
+
+    pub fn is_winning_move(&self, column: u32) -> bool {
+        self.winning_position() & self.possible() & self.column_mask(column) != 0
+    }
+
+    /// Whether the player to move can win immediately.
+    pub fn can_win_next(&self) -> bool {
+        self.winning_position() & self.possible() != 0
+    }
+
+    /// Whether the player who just moved has four in a row.
+    pub fn opponent_has_won(&self) -> bool {
+        has_alignment(self.current ^ self.mask, self.h)
+    }
+
+    /// Legal moves that do not hand the opponent an immediate win, as a bitmap of cells.
+    /// Assumes the player to move cannot win immediately.
+    fn possible_non_losing_moves(&self) -> u64 {
+        let mut possible = self.possible();
+        let opponent_win = self.opponent_winning_position();
+        let forced = possible & opponent_win;
+        if forced != 0 {
+            if forced & (forced - 1) != 0 {
+                //two immediate threats, nothing saves the game
+                return 0;
+            }
+            possible = forced;
+        }
+        //never play directly below an opponent threat
+        possible & !(opponent_win >> 1)
+    }
+
+    /// How many cells would complete four for the player to move after playing `bit`.
+    fn move_score(&self, bit: u64) -> u32 {
+        self.compute_winning_position(self.current | bit, self.mask).count_ones()
+    }
+
+    fn possible(&self) -> u64 {
+        (self.mask + self.bottom) & self.board
+    }
+
+    fn winning_position(&self) -> u64 {
+        self.compute_winning_position(self.current, self.mask)
+    }
+
+    fn opponent_winning_position(&self) -> u64 {
+        self.compute_winning_position(self.current ^ self.mask, self.mask)
+    }
+
+    /// Empty cells that would complete four in a row for the owner of `position`.
+    fn compute_winning_position(&self, position: u64, mask: u64) -> u64 {
+        let h = self.h;
+        //vertical
+        let mut r = shl(position, 1) & shl(position, 2) & shl(position, 3);
+
+        //horizontal and both diagonals, each a shift between neighbouring cells of a line
+        for &shift in &[h + 1, h, h + 2] {
+            let mut p = shl(position, shift) & shl(position, 2 * shift);
+            r |= p & shl(position, 3 * shift);
+            r |= p & shr(position, shift);
+            p = shr(position, shift) & shr(position, 2 * shift);
+            r |= p & shl(position, shift);
+            r |= p & shr(position, 3 * shift);
+        }
+
+        r & (self.board ^ mask)
+    }
+
+    fn bit(&self, column: u32, row: u32) -> u32 {
+        column * (self.h + 1) + row
+    }
+
+    fn bottom_mask_col(&self, column: u32) -> u64 {
+        1 << self.bit(column, 0)
+    }
+
+    fn top_mask(&self, column: u32) -> u64 {
+        1 << self.bit(column, self.h - 1)
+    }
+
+    fn column_mask(&self, column: u32) -> u64 {
+        ((1 << self.h) - 1) << self.bit(column, 0)
+    }
+}
+
+fn shl(x: u64, n: u32) -> u64 {
+    if n >= 64 { 0 } else { x << n }
+}
+
+fn shr(x: u64, n: u32) -> u64 {
+    if n >= 64 { 0 } else { x >> n }
+}
+
+fn has_alignment(position: u64, h: u32) -> bool {
+    for &shift in &[1, h + 1, h, h + 2] {
+        let m = position & shr(position, shift);
+        if m & shr(m, 2 * shift) != 0 {
+            return true;
+        }
+    }
+    false
+}
+
+/// The exact value of a position for the player to move.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Solution {
+    pub outcome: Outcome,
+    /// Plies until the game ends with perfect play: the winner wins as quickly as possible and
+    /// the loser holds out as long as possible.
+    pub plies: u32,
+    /// Positive for a win, negative for a loss, zero for a draw. The earlier the win, the larger
+    /// the score: winning with your last possible stone scores 1.
+    pub score: i32,
+}
+
+/// An exact Connect Four solver: negamax with alpha-beta pruning and null-window searches that
+/// close in on the score from zero outwards. A table holds lower and upper bounds on the scores
+/// of positions searched, shared between mirror images. Moves that hand the opponent a win are
+/// never searched, and the rest are tried in order of the threats they make.
+///
+/// Works on any board with `w * (h + 1) <= 64`, which includes the standard 7x6. On that board
+/// positions from the middle game on solve in well under a second in a release build, positions
+/// eight plies in in a second or two, and the empty board in about seven minutes with a 256 MB
+/// table.
+pub struct Solver {
+    table: SolverTable,
+    //the board size the table's entries are for, as keys are only unique within one size
+    size: (u32, u32),
+    nodes: u64,
+}
+
+impl Solver {
+    /// A solver whose table uses roughly `table_size` bytes.
+    pub fn new(table_size: usize) -> Solver {
+        Solver {
+            table: SolverTable::new(table_size),
+            size: (0, 0),
+            nodes: 0,
+        }
+    }
+
+    /// Positions visited since the solver was created.
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Solves `board` with `turn` to move. The game must not be over already.
+    pub fn solve(&mut self, board: &Board, turn: Player) -> Solution {
+        let position = Position::from_board(board, turn);
+        let score = self.solve_position(&position, false);
+        solution(&position, score)
+    }
+
+    /// Finds only whether `board` is a win, loss or draw for `turn`, which is much quicker
+    /// than `solve`.
+    pub fn solve_weak(&mut self, board: &Board, turn: Player) -> Outcome {
+        let position = Position::from_board(board, turn);
+        outcome(self.solve_position(&position, true))
+    }
+
+    /// The exact score of every legal move, in column order.
+    pub fn analyse(&mut self, board: &Board, turn: Player) -> Vec<(u32, Solution)> {
+        let position = Position::from_board(board, turn);
+        let mut scores = Vec::new();
+        for column in 0..position.w {
+            if !position.can_play(column) {
+                continue;
+            }
+            let mut next = position;
+            next.play(column);
+            let s = if position.is_winning_move(column) {
+                Solution {
+                    outcome: Outcome::Win,
+                    plies: 1,
+                    score: win_now_score(&position),
+                }
+            } else if next.is_full() {
+                Solution {
+                    outcome: Outcome::Draw,
+                    plies: 1,
+                    score: 0,
+                }
+            } else {
+                let reply = solution(&next, self.solve_position(&next, false));
+                Solution {
+                    outcome: outcome(-reply.score),
+                    plies: reply.plies + 1,
+                    score: -reply.score,
+                }
+            };
+            scores.push((column, s));
+        }
+        scores
+    }
+
+    /// A move that keeps the exact value of the position, so the quickest win or the slowest
+    /// loss, preferring central columns. `None` if `turn` has no legal move.
+    ///
+    /// Cheaper than `analyse`: each move is only checked against the position's score.
+    pub fn best_move(&mut self, board: &Board, turn: Player) -> Option<u32> {
+        let position = Position::from_board(board, turn);
+        if position.is_full() {
+            return None;
+        }
+        let score = self.solve_position(&position, false);
+        centre_order(position.w)
+            .into_iter()
+            .find(|&column| position.can_play(column) && self.reaches(&position, column, score))
+    }
+
+    /// Whether playing `column` scores at least `score` for the player to move.
+    fn reaches(&mut self, position: &Position, column: u32, score: i32) -> bool {
+        if position.is_winning_move(column) {
+            return win_now_score(position) >= score;
+        }
+        let mut next = *position;
+        next.play(column);
+        if next.is_full() {
+            return 0 >= score;
+        }
+        if next.can_win_next() {
+            return -win_now_score(&next) >= score;
+        }
+        self.negamax(&next, -score, -score + 1) <= -score
+    }
+
+    fn solve_position(&mut self, position: &Position, weak: bool) -> i32 {
+        if self.size != (position.w, position.h) {
+            self.table.clear();
+            self.size = (position.w, position.h);
+        }
+        if position.can_win_next() {
+            return win_now_score(position);
+        }
+        let cells = (position.w * position.h) as i32;
+        let moves = position.moves as i32;
+        let mut min = -(cells - moves) / 2;
+        let mut max = (cells + 1 - moves) / 2;
+        if weak {
+            min = -1;
+            max = 1;
+        }
+
+        //narrow the window with null-window searches, probing closer to zero first
+        while min < max {
+            let mut med = min + (max - min) / 2;
+            if med <= 0 && min / 2 < med {
+                med = min / 2;
+            } else if med >= 0 && max / 2 > med {
+                med = max / 2;
+            }
+            let r = self.negamax(position, med, med + 1);
+            if r <= med {
+                max = r;
+            } else {
+                min = r;
+            }
+        }
+        min
+    }
+
+    /// Assumes the player to move cannot win immediately.
+    fn negamax(&mut self, position: &Position, mut alpha: i32, mut beta: i32) -> i32 {
+        self.nodes += 1;
+        let cells = (position.w * position.h) as i32;
+        let moves = position.moves as i32;
+
+        let next = position.possible_non_losing_moves();
+        if next == 0 {
+            return -(cells - moves) / 2;
+        }
+        if moves >= cells - 2 {
+            return 0;
+        }
+
+        //mirror images have the same score, so they share an entry
+        let key = position.key().min(position.mirror_key());
+        let mut min = -(cells - 2 - moves) / 2;
+        let mut max = (cells - 1 - moves) / 2;
+        if let Some((lower, upper)) = self.table.probe(key) {
+            min = min.max(lower);
+            max = max.min(upper);
+        }
+        if alpha < min {
+            alpha = min;
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+        if beta > max {
+            beta = max;
+            if alpha >= beta {
+                return beta;
+            }
+        }
+
+        //most new threats first, then the most central column
+        let mut candidates = [(0, 0); 32];
+        let mut count = 0;
+        for i in 0..position.w {
+            let column = centre_column(position.w, i);
+            let bit = next & position.column_mask(column);
+            if bit == 0 {
+                continue;
+            }
+            //insertion sort, stable so equal scores stay in centre-first order
+            let score = position.move_score(bit);
+            let mut j = count;
+            while j > 0 && candidates[j - 1].1 < score {
+                candidates[j] = candidates[j - 1];
+                j -= 1;
+            }
+            candidates[j] = (column, score);
+            count += 1;
+        }
+
+        //a child already known to refute the window saves searching any of them
+        for &(column, _) in &candidates[..count] {
+            let mut child = *position;
+            child.play(column);
+            if let Some((_, upper)) = self.table.probe(child.key().min(child.mirror_key())) {
+                if -upper >= beta {
+                    self.table.store(key, -upper, max);
+                    return -upper;
+                }
+            }
+        }
+
+        for &(column, _) in &candidates[..count] {
+            let mut child = *position;
+            child.play(column);
+            let score = -self.negamax(&child, -beta, -alpha);
+            if score >= beta {
+                self.table.store(key, score, max);
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        self.table.store(key, min, alpha);
+        alpha
+    }
+}
+
+/// Bounds on the scores of solved positions, keyed on `Position::key`. Each key maps to a single
+/// slot, and a new entry always replaces the old one.
+struct SolverTable {
+    entries: Vec<SolverEntry>,
+}
+
+#[derive(Copy, Clone)]
+struct SolverEntry {
+    key: u64,
+    lower: i8,
+    upper: i8,
+    used: bool,
+}
+
+const EMPTY_ENTRY: SolverEntry = SolverEntry {
+    key: 0,
+    lower: 0,
+    upper: 0,
+    used: false,
+};
+
+impl SolverTable {
+    fn new(size_in_bytes: usize) -> SolverTable {
+        let slots = (size_in_bytes / ::std::mem::size_of::<SolverEntry>()).max(1);
+        SolverTable {
+            entries: vec![EMPTY_ENTRY; slots],
+        }
+    }
+
+    /// The lower and upper bounds stored for `key`.
+    fn probe(&self, key: u64) -> Option<(i32, i32)> {
+        let entry = self.entries[self.slot(key)];
+        if entry.used && entry.key == key {
+            Some((entry.lower as i32, entry.upper as i32))
+        } else {
+            None
+        }
+    }
+
+    fn store(&mut self, key: u64, lower: i32, upper: i32) {
+        let slot = self.slot(key);
+        self.entries[slot] = SolverEntry {
+            key: key,
+            lower: lower as i8,
+            upper: upper as i8,
+            used: true,
+        };
+    }
+
+    fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = EMPTY_ENTRY;
+        }
+    }
+
+    //keys differ mostly in their low bits, the first columns, so spread them out first
+    fn slot(&self, key: u64) -> usize {
+        (seed::mix(key) % self.entries.len() as u64) as usize
+    }
+}
+
+pub(crate) fn centre_order(w: u32) -> Vec<u32> {
+    (0..w).map(|i| centre_column(w, i)).collect()
+}
+
+/// The `i`th column outwards from the centre, alternating sides.
+fn centre_column(w: u32, i: u32) -> u32 {
+    let offset = ((i + 1) / 2) as i32;
+    let side = if i % 2 == 0 { 1 } else { -1 };
+    (w as i32 / 2 + side * offset) as u32
+}
+
+pub(crate) fn win_now_score(position: &Position) -> i32 {
+    ((position.w * position.h + 1 - position.moves) / 2) as i32
+}
+
+pub(crate) fn outcome(score: i32) -> Outcome {
+    if score > 0 {
+        Outcome::Win
+    } else if score < 0 {
+        Outcome::Loss
+    } else {
+        Outcome::Draw
+    }
+}
+
+pub(crate) fn solution(position: &Position, score: i32) -> Solution {
+    let cells = (position.w * position.h) as i32;
+    let moves = position.moves as i32;
+    let plies = if score > 0 {
+        2 * ((cells + 1 - moves) / 2 - score) + 1
+    } else if score < 0 {
+        2 * ((cells - moves) / 2 + score) + 2
+    } else {
+        cells - moves
+    };
+    Solution {
+        outcome: outcome(score),
+        plies: plies as u32,
+        score: score,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fixtures::random_position;
+    use rand::{SeedableRng, XorShiftRng};
+
+    //plain negamax over `Board`, returning the score and ply count the solver should find
+    fn brute_force(board: &Board, turn: Player) -> (i32, u32) {
+        let mut best: Option<(i32, u32)> = None;
+        for column in 0..board.w {
+            let mut next = match board.place(column, turn) {
+                Some(b) => b,
+                None => continue,
+            };
+            let result = if next.is_over(column) {
+                (((board.w * board.h + 1 - board.turn_number) / 2) as i32, 1)
+            } else if next.turn_number >= board.w * board.h {
+                (0, 1)
+            } else {
+                let (score, plies) = brute_force(&next, turn.switch());
+                (-score, plies + 1)
+            };
+            best = match best {
+                None => Some(result),
+                Some(b) => {
+                    //prefer higher scores, then quicker wins and slower losses or draws
+                    let better = result.0 > b.0 || result.0 == b.0 && if result.0 > 0 {
+                        result.1 < b.1
+                    } else {
+                        result.1 > b.1
+                    };
+                    if better { Some(result) } else { Some(b) }
+                }
+            };
+        }
+        best.unwrap()
+    }
+
+    #[test]
+    fn position_round_trip() {
+        let (board, turn) = Board::from_moves(7, 6, &[3, 3, 2, 4, 4, 4]).unwrap();
+        let position = Position::from_board(&board, turn);
+        assert_eq!(position.moves(), 6);
+        assert_eq!(position.to_board(turn).vector, board.vector);
+
+        let (mirrored, _) = Board::from_moves(7, 6, &[3, 3, 4, 2, 2, 2]).unwrap();
+        assert_eq!(position.mirror_key(), Position::from_board(&mirrored, turn).key());
+        assert!(position.key() != position.mirror_key());
+        assert_eq!(Position::from_key(7, 6, position.key()), position);
+        assert_eq!(Position::from_key(7, 6, 0), Position::empty(7, 6));
+    }
+
+    #[test]
+    fn detects_wins() {
+        let (board, turn) = Board::from_moves(7, 6, &[0, 1, 0, 1, 0, 1]).unwrap();
+        let position = Position::from_board(&board, turn);
+        assert!(position.is_winning_move(0));
+        assert!(!position.is_winning_move(2));
+
+        let mut after = position;
+        after.play(0);
+        assert!(after.opponent_has_won());
+        assert!(!position.opponent_has_won());
+    }
+
+    #[test]
+    fn agrees_with_brute_force_on_small_boards() {
+        let mut solver = Solver::new(1024 * 1024);
+        for &(w, h, stones) in &[(4, 4, 5), (5, 4, 9), (4, 5, 9), (6, 4, 13), (7, 6, 31)] {
+            for seed in 0..4 {
+                let (board, turn) = random_position(w, h, stones, &mut XorShiftRng::from_seed([seed, 1, 2, 3]));
+                let (score, plies) = brute_force(&board, turn);
+                let solution = solver.solve(&board, turn);
+                assert_eq!((solution.score, solution.plies), (score, plies), "{:?}", board.vector);
+                assert_eq!(solver.solve_weak(&board, turn), solution.outcome);
+            }
+        }
+    }
+
+    #[test]
+    fn best_move_keeps_the_outcome() {
+        let mut solver = Solver::new(1024 * 1024);
+        let (board, turn) = Board::from_moves(5, 4, &[2, 2, 1, 3, 3, 1, 0, 4]).unwrap();
+        let solution = solver.solve(&board, turn);
+
+        let column = solver.best_move(&board, turn).unwrap();
+        let analysis = solver.analyse(&board, turn);
+        let chosen = analysis.iter().find(|&&(c, _)| c == column).unwrap().1;
+        assert_eq!(chosen, solution);
+        assert!(analysis.iter().all(|&(_, s)| s.score <= solution.score));
+    }
+
+    #[test]
+    fn finds_forced_wins_on_the_standard_board() {
+        let mut solver = Solver::new(16 * 1024 * 1024);
+
+        //three in a row on the bottom with both ends open
+        let (board, turn) = Board::from_moves(7, 6, &[2, 2, 3, 3]).unwrap();
+        let solution = solver.solve(&board, turn);
+        assert_eq!(solution.outcome, Outcome::Win);
+        assert_eq!(solution.plies, 3);
+        let best = solver.best_move(&board, turn).unwrap();
+        assert!(best == 1 || best == 4);
+    }
+
+    //positions from published solutions, columns numbered from 1
+    #[test]
+    fn agrees_with_published_values() {
+        let mut solver = Solver::new(16 * 1024 * 1024);
+        let published = |moves: &str| {
+            let moves: Vec<u32> = parse_moves(moves).unwrap().iter().map(|c| c - 1).collect();
+            Board::from_moves(7, 6, &moves).unwrap()
+        };
+
+        //end games from Pascal Pons' benchmark set Test_L3_R1, with their scores
+        for &(moves, score) in &[("2252576253462244111563365343671351441", -1), ("7422341735647741166133573473242566", 1)] {
+            let (board, turn) = published(moves);
+            assert_eq!(solver.solve(&board, turn).score, score, "{}", moves);
+        }
+
+        //the first position of John Tromp's database of 8-ply positions, a win for the first player
+        let (board, turn) = published("44444433");
+        assert_eq!(solver.solve_weak(&board, turn), Outcome::Win);
+    }
+
+    //the published value of the empty board: the first player wins with their last stone, by
+    //starting in the centre
+    #[test]
+    fn solves_the_empty_standard_board() {
+        let mut solver = Solver::new(256 * 1024 * 1024);
+        let (board, turn) = Board::from_moves(7, 6, &[]).unwrap();
+
+        let solution = solver.solve(&board, turn);
+        assert_eq!((solution.outcome, solution.plies, solution.score), (Outcome::Win, 41, 1));
+        assert_eq!(solver.best_move(&board, turn), Some(3));
+    }
+}
@@ -0,0 +1,110 @@
+use std::ops::AddAssign;
+use std::time::Duration;
+
+/// Counters for the work done by an alpha-beta search, over one depth of iterative deepening
+/// or a whole search.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchStats {
+    /// Every position visited, finished games included.
+    pub nodes: u64,
+    /// Finished games reached.
+    pub terminals: u64,
+    /// Positions at the search horizon, scored by rollouts or the evaluator.
+    pub leaves: u64,
+    pub rollouts: u64,
+    /// Positions scored by `SearchConfig::evaluator`, batched or not.
+    pub evaluations: u64,
+    /// Nodes where a move fell outside the window, so the remaining moves were skipped.
+    pub cutoffs: u64,
+    /// Cutoffs caused by the first move searched, a measure of move ordering.
+    pub first_move_cutoffs: u64,
+    pub table_probes: u64,
+    pub table_hits: u64,
+    /// Table hits that settled a node without searching it.
+    pub table_cutoffs: u64,
+    /// Extra searches of a position: PVS and aspiration windows that failed, and MTD(f) passes
+    /// after the first.
+    pub re_searches: u64,
+    pub time: Duration,
+}
+
+impl SearchStats {
+    /// The share of transposition table probes that found the position, from 0 to 1.
+    pub fn table_hit_rate(&self) -> f32 {
+        ratio(self.table_hits, self.table_probes)
+    }
+
+    /// The share of cutoffs made by the first move, from 0 to 1.
+    pub fn first_move_cutoff_rate(&self) -> f32 {
+        ratio(self.first_move_cutoffs, self.cutoffs)
+    }
+
+    pub fn nodes_per_second(&self) -> f64 {
+        let secs = self.time.as_secs() as f64 + self.time.subsec_nanos() as f64 * 1e-9;
+        if secs > 0. { self.nodes as f64 / secs } else { 0. }
+    }
+}
+
+impl<'a> AddAssign<&'a SearchStats> for SearchStats {
+    fn add_assign(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.terminals += other.terminals;
+        self.leaves += other.leaves;
+        self.rollouts += other.rollouts;
+        self.evaluations += other.evaluations;
+        self.cutoffs += other.cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.table_probes += other.table_probes;
+        self.table_hits += other.table_hits;
+        self.table_cutoffs += other.table_cutoffs;
+        self.re_searches += other.re_searches;
+        self.time += other.time;
+    }
+}
+
+/// The effective branching factor of iterative deepening: how many times more nodes the last
+/// depth took than the one before. `None` with fewer than two depths.
+pub fn effective_branching_factor(depths: &[SearchStats]) -> Option<f32> {
+    match depths.len() {
+        0 | 1 => None,
+        n => Some(ratio(depths[n - 1].nodes, depths[n - 2].nodes)),
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f32 {
+    if b == 0 { 0. } else { a as f32 / b as f32 }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sums_and_rates() {
+        let mut total = SearchStats::default();
+        let depth = SearchStats {
+            nodes: 100,
+            cutoffs: 10,
+            first_move_cutoffs: 8,
+            table_probes: 50,
+            table_hits: 5,
+            time: Duration::from_millis(10),
+            ..SearchStats::default()
+        };
+        total += &depth;
+        total += &depth;
+        assert_eq!(total.nodes, 200);
+        assert_eq!(total.time, Duration::from_millis(20));
+        assert_eq!(total.table_hit_rate(), 0.1);
+        assert_eq!(total.first_move_cutoff_rate(), 0.8);
+        assert!((total.nodes_per_second() - 10000.).abs() < 1e-6);
+        assert_eq!(SearchStats::default().table_hit_rate(), 0.);
+
+        let deeper = SearchStats {
+            nodes: 400,
+            ..SearchStats::default()
+        };
+        assert_eq!(effective_branching_factor(&[depth.clone()]), None);
+        assert_eq!(effective_branching_factor(&[depth, deeper]), Some(4.));
+    }
+}
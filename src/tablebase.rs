@@ -0,0 +1,555 @@
+use bytes::*;
+use c4::*;
+use solver::*;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const MAGIC: &'static [u8; 4] = b"C4TB";
+const VERSION: u8 = 1;
+const HEADER_SIZE: u64 = 8;
+//a key and its score
+const RECORD_SIZE: u64 = 9;
+//positions expanded at a time while building on disk
+const CHUNK: usize = 1 << 22;
+
+/// The exact score of every position reachable on a small board, found by retrograde analysis:
+/// every reachable position is enumerated first, then scored from the last stone back to the
+/// empty board, so each position is scored from its already scored children.
+///
+/// Scores are the solver's, see `solver::Solution`. Positions are stored once per mirror pair,
+/// as a sorted key list per stone count, at nine bytes each. With mirrors folded, 4x4 has 67
+/// thousand positions and builds in a tenth of a second, 5x4 has 1.5 million and takes a few
+/// seconds, 6x4 has 35 million and takes minutes.
+///
+/// Larger boards don't fit in memory, so `build_in` keeps them on disk instead, one file per
+/// stone count, and the tablebase it returns reads positions from there. 6x5 has 940 million
+/// positions, which take 8 GB of files and about half an hour to build with 2 GB of memory.
+pub struct Tablebase {
+    w: u32,
+    h: u32,
+    layers: Vec<Layer>,
+}
+
+//positions with the same number of stones, where the game is still going, sorted by key
+enum Layer {
+    Memory { keys: Vec<u64>, scores: Vec<i8> },
+    //the same as records of a key and a score, after the header
+    File { path: PathBuf, file: Mutex<File>, len: usize },
+}
+
+impl Tablebase {
+    pub fn build(w: u32, h: u32) -> Tablebase {
+        assert_log("board too large for a tablebase", Position::fits(w, h));
+
+        //forwards: the key of every position where the game is still going, by stone count
+        let mut positions = Vec::new();
+        let mut frontier = vec![Position::empty(w, h).key()];
+        while !frontier.is_empty() {
+            let mut next = Vec::new();
+            for &key in &frontier {
+                push_children(w, h, key, &mut next);
+            }
+            next.sort();
+            next.dedup();
+            positions.push(frontier);
+            frontier = next;
+        }
+
+        //backwards: score the fullest layer first, so every child is scored before its parent
+        let mut tablebase = Tablebase {
+            w: w,
+            h: h,
+            layers: (0..positions.len()).map(|_| Layer::empty()).collect(),
+        };
+        while let Some(keys) = positions.pop() {
+            let scores = tablebase.score_layer(&keys);
+            tablebase.layers[positions.len()] = Layer::Memory {
+                keys: keys,
+                scores: scores,
+            };
+        }
+        tablebase
+    }
+
+    /// Builds the tablebase for a `w` by `h` board in the directory `dir`, then opens it from
+    /// there. Only the stone count being worked on and the one after it are held in memory, the
+    /// rest goes through files, so this reaches boards `build` can't.
+    pub fn build_in<P: AsRef<Path>>(w: u32, h: u32, dir: P) -> io::Result<Tablebase> {
+        assert_log("board too large for a tablebase", Position::fits(w, h));
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        //forwards, as in `build`, expanding each stone count in chunks sorted into runs, which
+        //are merged into the keys of the next
+        let mut counts = vec![write_keys(&keys_path(dir, 0), &[Position::empty(w, h).key()])?];
+        loop {
+            let stones = counts.len() - 1;
+            let mut input = BufReader::new(File::open(keys_path(dir, stones))?);
+            let mut runs = Vec::new();
+            let mut left = counts[stones];
+            while left > 0 {
+                let chunk = read_keys(&mut input, left.min(CHUNK))?;
+                left -= chunk.len();
+                let mut next = Vec::new();
+                for &key in &chunk {
+                    push_children(w, h, key, &mut next);
+                }
+                next.sort_unstable();
+                next.dedup();
+                let run = dir.join(format!("run-{}", runs.len()));
+                let count = write_keys(&run, &next)?;
+                runs.push((run, count));
+            }
+            let count = merge_keys(&runs, &keys_path(dir, stones + 1))?;
+            for run in &runs {
+                fs::remove_file(&run.0)?;
+            }
+            if count == 0 {
+                fs::remove_file(keys_path(dir, stones + 1))?;
+                break;
+            }
+            counts.push(count);
+        }
+
+        //backwards, holding only the stone count being scored and the one after it
+        let mut tablebase = Tablebase {
+            w: w,
+            h: h,
+            layers: counts.iter().map(|_| Layer::empty()).collect(),
+        };
+        for stones in (0..counts.len()).rev() {
+            let keys = read_keys(&mut BufReader::new(File::open(keys_path(dir, stones))?), counts[stones])?;
+            let scores = tablebase.score_layer(&keys);
+            write_layer(&layer_path(dir, stones), w, h, stones, &keys, &scores)?;
+            fs::remove_file(keys_path(dir, stones))?;
+            tablebase.layers[stones] = Layer::Memory {
+                keys: keys,
+                scores: scores,
+            };
+            if stones + 1 < counts.len() {
+                tablebase.layers[stones + 1] = Layer::empty();
+            }
+        }
+        Tablebase::open(dir)
+    }
+
+    /// Opens a tablebase `build_in` wrote to `dir`. Positions are looked up in the files, so
+    /// each lookup reads from disk.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Tablebase> {
+        let dir = dir.as_ref();
+        let mut size = None;
+        let mut layers = Vec::new();
+        while layer_path(dir, layers.len()).exists() {
+            let path = layer_path(dir, layers.len());
+            let mut file = File::open(&path)?;
+            let mut header = [0; HEADER_SIZE as usize];
+            file.read_exact(&mut header)?;
+            if &header[..4] != MAGIC {
+                return Err(invalid("not a tablebase file"));
+            }
+            if header[4] != VERSION {
+                return Err(invalid("unsupported tablebase file version"));
+            }
+            let (w, h) = (header[5] as u32, header[6] as u32);
+            if header[7] as usize != layers.len() || size.is_some() && size != Some((w, h)) {
+                return Err(invalid("tablebase files don't match"));
+            }
+            size = Some((w, h));
+            let records = file.metadata()?.len() - HEADER_SIZE;
+            if records % RECORD_SIZE != 0 {
+                return Err(invalid("truncated tablebase file"));
+            }
+            layers.push(Layer::File {
+                path: path,
+                file: Mutex::new(file),
+                len: (records / RECORD_SIZE) as usize,
+            });
+        }
+        let (w, h) = size.ok_or_else(|| invalid("no tablebase files"))?;
+        Ok(Tablebase {
+            w: w,
+            h: h,
+            layers: layers,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.w
+    }
+
+    pub fn height(&self) -> u32 {
+        self.h
+    }
+
+    /// Number of stored positions, with mirror images counted once.
+    pub fn len(&self) -> usize {
+        self.layers.iter().map(|l| l.len()).sum()
+    }
+
+    /// Every stored position, one of each mirror pair, emptiest first.
+    pub fn positions<'a>(&'a self) -> Box<Iterator<Item = Position> + 'a> {
+        let (w, h) = (self.w, self.h);
+        Box::new(
+            self.layers
+                .iter()
+                .flat_map(|l| l.keys())
+                .map(move |key| Position::from_key(w, h, key)),
+        )
+    }
+
+    /// The value of `board` with `turn` to move. `None` for other board sizes, for finished
+    /// games and for positions that cannot come up in a game.
+    pub fn solve(&self, board: &Board, turn: Player) -> Option<Solution> {
+        let position = self.position(board, turn)?;
+        self.score(&position).map(|score| solution(&position, score))
+    }
+
+    /// The value of every legal move, in column order.
+    pub fn analyse(&self, board: &Board, turn: Player) -> Option<Vec<(u32, Solution)>> {
+        let position = self.position(board, turn)?;
+        self.score(&position)?;
+        Some(
+            (0..self.w)
+                .filter_map(|column| {
+                    self.move_score(&position, column)
+                        .map(|score| (column, solution(&position, score)))
+                })
+                .collect(),
+        )
+    }
+
+    /// A move that keeps the exact value of the position, preferring central columns, the same
+    /// move `Solver::best_move` picks.
+    pub fn best_move(&self, board: &Board, turn: Player) -> Option<u32> {
+        let position = self.position(board, turn)?;
+        self.best_position_move(&position)
+    }
+
+    /// The moves of a perfectly played game from `board`, to the end.
+    pub fn principal_variation(&self, board: &Board, turn: Player) -> Vec<u32> {
+        let mut line = Vec::new();
+        let mut position = match self.position(board, turn) {
+            Some(position) => position,
+            None => return line,
+        };
+        while let Some(column) = self.best_position_move(&position) {
+            line.push(column);
+            if position.is_winning_move(column) {
+                break;
+            }
+            position.play(column);
+        }
+        line
+    }
+
+    fn position(&self, board: &Board, turn: Player) -> Option<Position> {
+        if board.w != self.w || board.h != self.h {
+            return None;
+        }
+        Some(Position::from_board(board, turn))
+    }
+
+    fn best_position_move(&self, position: &Position) -> Option<u32> {
+        let score = self.score(position)?;
+        centre_order(self.w)
+            .into_iter()
+            .find(|&column| self.move_score(position, column) == Some(score))
+    }
+
+    fn score(&self, position: &Position) -> Option<i32> {
+        let layer = self.layers.get(position.moves() as usize)?;
+        layer.score(canonical(*position).key()).map(|score| score as i32)
+    }
+
+    //the scores of positions whose children are all scored
+    fn score_layer(&self, keys: &[u64]) -> Vec<i8> {
+        keys.iter()
+            .map(|&key| {
+                let position = Position::from_key(self.w, self.h, key);
+                (0..self.w)
+                    .filter_map(|column| self.move_score(&position, column))
+                    .max()
+                    .expect("position with no legal move") as i8
+            })
+            .collect()
+    }
+
+    /// The score of playing `column`, once every position after it has been scored.
+    fn move_score(&self, position: &Position, column: u32) -> Option<i32> {
+        if !position.can_play(column) {
+            return None;
+        }
+        if position.is_winning_move(column) {
+            return Some(win_now_score(position));
+        }
+        let mut child = *position;
+        child.play(column);
+        if child.is_full() {
+            return Some(0);
+        }
+        Some(-self.score(&child).expect("child scored before its parent"))
+    }
+}
+
+impl Layer {
+    fn empty() -> Layer {
+        Layer::Memory {
+            keys: Vec::new(),
+            scores: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Layer::Memory { ref keys, .. } => keys.len(),
+            Layer::File { len, .. } => len,
+        }
+    }
+
+    fn score(&self, key: u64) -> Option<i8> {
+        match *self {
+            Layer::Memory { ref keys, ref scores } => keys.binary_search(&key).ok().map(|i| scores[i]),
+            Layer::File { ref file, len, .. } => {
+                let mut file = file.lock().unwrap();
+                let (mut low, mut high) = (0, len);
+                while low < high {
+                    let middle = low + (high - low) / 2;
+                    let (k, score) = read_record(&mut file, middle).expect("could not read tablebase file");
+                    if k == key {
+                        return Some(score);
+                    }
+                    if k < key {
+                        low = middle + 1;
+                    } else {
+                        high = middle;
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    fn keys<'a>(&'a self) -> Box<Iterator<Item = u64> + 'a> {
+        match *self {
+            Layer::Memory { ref keys, .. } => Box::new(keys.iter().cloned()),
+            Layer::File { ref path, len, .. } => {
+                let mut input = BufReader::new(File::open(path).expect("could not open tablebase file"));
+                input.seek(SeekFrom::Start(HEADER_SIZE)).expect("could not read tablebase file");
+                Box::new((0..len).map(move |_| {
+                    let mut record = [0; RECORD_SIZE as usize];
+                    input.read_exact(&mut record).expect("could not read tablebase file");
+                    from_u64_bytes(&record[..8])
+                }))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Tablebase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tablebase {{ w: {}, h: {}, positions: {} }}", self.w, self.h, self.len())
+    }
+}
+
+//adds the keys of the positions one stone on from `key` where the game is still going
+fn push_children(w: u32, h: u32, key: u64, out: &mut Vec<u64>) {
+    let position = Position::from_key(w, h, key);
+    for column in 0..w {
+        if !position.can_play(column) || position.is_winning_move(column) {
+            continue;
+        }
+        let mut child = position;
+        child.play(column);
+        if !child.is_full() {
+            out.push(canonical(child).key());
+        }
+    }
+}
+
+fn canonical(position: Position) -> Position {
+    let mirror = position.mirror();
+    if mirror.key() < position.key() {
+        mirror
+    } else {
+        position
+    }
+}
+
+fn keys_path(dir: &Path, stones: usize) -> PathBuf {
+    dir.join(format!("keys-{:02}", stones))
+}
+
+fn layer_path(dir: &Path, stones: usize) -> PathBuf {
+    dir.join(format!("layer-{:02}.c4tb", stones))
+}
+
+fn write_keys(path: &Path, keys: &[u64]) -> io::Result<usize> {
+    let mut out = BufWriter::new(File::create(path)?);
+    for &key in keys {
+        out.write_all(&u64_bytes(key))?;
+    }
+    out.flush()?;
+    Ok(keys.len())
+}
+
+fn read_keys<R: Read>(input: &mut R, count: usize) -> io::Result<Vec<u64>> {
+    (0..count).map(|_| read_u64(input)).collect()
+}
+
+//merges sorted runs of keys, each with its length, into one file without duplicates, and
+//returns how many keys that holds
+fn merge_keys(runs: &[(PathBuf, usize)], path: &Path) -> io::Result<usize> {
+    let mut inputs = Vec::new();
+    let mut heap = BinaryHeap::new();
+    for (i, &(ref run, count)) in runs.iter().enumerate() {
+        let mut input = BufReader::new(File::open(run)?);
+        if count > 0 {
+            heap.push(Reverse((read_u64(&mut input)?, i)));
+        }
+        inputs.push((input, count.saturating_sub(1)));
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut last = None;
+    let mut count = 0;
+    while let Some(Reverse((key, i))) = heap.pop() {
+        if last != Some(key) {
+            out.write_all(&u64_bytes(key))?;
+            last = Some(key);
+            count += 1;
+        }
+        let (ref mut input, ref mut left) = inputs[i];
+        if *left > 0 {
+            *left -= 1;
+            heap.push(Reverse((read_u64(input)?, i)));
+        }
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+fn write_layer(path: &Path, w: u32, h: u32, stones: usize, keys: &[u64], scores: &[i8]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION, w as u8, h as u8, stones as u8])?;
+    for (&key, &score) in keys.iter().zip(scores) {
+        out.write_all(&u64_bytes(key))?;
+        out.write_all(&[score as u8])?;
+    }
+    out.flush()
+}
+
+fn read_record(file: &mut File, i: usize) -> io::Result<(u64, i8)> {
+    file.seek(SeekFrom::Start(HEADER_SIZE + i as u64 * RECORD_SIZE))?;
+    let mut record = [0; RECORD_SIZE as usize];
+    file.read_exact(&mut record)?;
+    Ok((from_u64_bytes(&record[..8]), record[8] as i8))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::SearchConfig;
+    use mcts::{search, Outcome};
+    use fixtures::random_position;
+    use rand::{SeedableRng, XorShiftRng};
+    use std::env;
+    use std::sync::Arc;
+
+    //seeded random positions where the game is still going, with `stones` stones
+    fn random_positions(w: u32, h: u32, stones: u32, count: u32) -> Vec<(Board, Player)> {
+        let mut rng = XorShiftRng::from_seed([w, h, stones, 1]);
+        (0..count).map(|_| random_position(w, h, stones, &mut rng)).collect()
+    }
+
+    #[test]
+    fn agrees_with_the_solver() {
+        let tablebase = Tablebase::build(4, 4);
+        let mut solver = Solver::new(1024 * 1024);
+        for stones in 0..14 {
+            for (board, turn) in random_positions(4, 4, stones, 5) {
+                assert_eq!(tablebase.solve(&board, turn), Some(solver.solve(&board, turn)));
+                assert_eq!(tablebase.analyse(&board, turn), Some(solver.analyse(&board, turn)));
+                assert_eq!(tablebase.best_move(&board, turn), solver.best_move(&board, turn));
+            }
+        }
+    }
+
+    #[test]
+    fn covers_exactly_the_games_in_progress() {
+        let tablebase = Tablebase::build(4, 4);
+        let empty = Board::from_int_array(vec![0; 16], 4, 4);
+        assert!(tablebase.solve(&empty, Player::P1).is_some());
+
+        //four in a row already
+        let won = Board::from_int_array(vec![1, 1, 1, 1, -1, -1, -1, 0, 0, 0, 0, 0, 0, 0, 0, 0], 4, 4);
+        assert!(tablebase.solve(&won, Player::P2).is_none());
+        assert!(tablebase.solve(&empty, Player::P1).is_some());
+        let wrong_size = Board::from_int_array(vec![0; 20], 5, 4);
+        assert!(tablebase.solve(&wrong_size, Player::P1).is_none());
+
+        for position in tablebase.positions() {
+            assert!(!position.opponent_has_won() && !position.is_full());
+        }
+    }
+
+    #[test]
+    fn builds_on_disk() {
+        let dir = env::temp_dir().join(format!("c4-tablebase-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let memory = Tablebase::build(4, 4);
+        let disk = Tablebase::build_in(4, 4, &dir).unwrap();
+        assert_eq!(disk.len(), memory.len());
+        assert!(disk.positions().eq(memory.positions()));
+        for position in memory.positions().step_by(11) {
+            let board = position.to_board(Player::P1);
+            assert_eq!(disk.analyse(&board, Player::P1), memory.analyse(&board, Player::P1));
+        }
+        let wrong_size = Board::from_int_array(vec![0; 20], 5, 4);
+        assert!(disk.solve(&wrong_size, Player::P1).is_none());
+
+        //the last file cut short
+        let last = layer_path(&dir, memory.layers.len() - 1);
+        let bytes = fs::read(&last).unwrap();
+        fs::write(&last, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(Tablebase::open(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(Tablebase::open(&dir).is_err());
+    }
+
+    #[test]
+    fn principal_variation_reaches_the_end() {
+        let tablebase = Tablebase::build(4, 4);
+        let (board, turn) = random_positions(4, 4, 3, 1).pop().unwrap();
+        let solution = tablebase.solve(&board, turn).unwrap();
+        let line = tablebase.principal_variation(&board, turn);
+        assert_eq!(line.len() as u32, solution.plies);
+
+        let mut b = board;
+        let mut p = turn;
+        for (i, &column) in line.iter().enumerate() {
+            b = b.place(column, p).unwrap();
+            assert_eq!(b.is_over(column), i + 1 == line.len() && solution.outcome != Outcome::Draw);
+            p = p.switch();
+        }
+    }
+
+    #[test]
+    fn search_plays_from_the_tablebase() {
+        let config = SearchConfig {
+            tablebase: Some(Arc::new(Tablebase::build(4, 4))),
+            ..SearchConfig::default()
+        };
+        let board = Board::from_int_array(vec![0; 16], 4, 4);
+        let result = search(&board, Player::P1, &config).unwrap();
+        assert!(result.precomputed);
+        assert_eq!(result.nodes, 0);
+        assert_eq!(result.move_scores.len(), 4);
+        assert!(result.proven.is_some());
+    }
+}
@@ -0,0 +1,606 @@
+use bytes::*;
+use c4::*;
+use network::*;
+use uct::*;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+const REPLAY_MAGIC: &'static [u8; 4] = b"C4RP";
+const REPLAY_VERSION: u8 = 1;
+
+/// Everything that controls a self-play training run.
+#[derive(Clone, Debug)]
+pub struct TrainingConfig {
+    pub w: u32,
+    pub h: u32,
+    /// Hidden layer sizes of the network trained from scratch.
+    pub hidden: Vec<usize>,
+    pub games_per_iteration: u32,
+    /// PUCT playouts per move, in self-play and in gating matches.
+    pub playouts: u32,
+    pub exploration: f32,
+    /// For this many plies of each self-play game moves are sampled in proportion to their
+    /// visits, for variety; after that the best move is played.
+    pub sampled_plies: u32,
+    pub learning_rate: f32,
+    /// Passes over the replay buffer per iteration.
+    pub epochs: u32,
+    pub batch_size: usize,
+    /// The most positions kept for training, oldest dropped first.
+    pub replay_size: usize,
+    pub gating_games: u32,
+    /// The score out of 1 a new network needs against the current best to replace it.
+    pub gating_threshold: f32,
+    /// Where networks, the replay buffer and a log are written after every iteration, and read
+    /// back from when a run is resumed. `None` keeps everything in memory.
+    pub checkpoint_dir: Option<PathBuf>,
+    pub seed: u32,
+}
+
+impl Default for TrainingConfig {
+    /// A run on 5x4 boards that makes visible progress in minutes.
+    fn default() -> TrainingConfig {
+        TrainingConfig {
+            w: 5,
+            h: 4,
+            hidden: vec![64, 64],
+            games_per_iteration: 50,
+            playouts: 200,
+            exploration: 1.5,
+            sampled_plies: 4,
+            learning_rate: 0.01,
+            epochs: 2,
+            batch_size: 32,
+            replay_size: 20000,
+            gating_games: 20,
+            gating_threshold: 0.55,
+            checkpoint_dir: None,
+            seed: 1,
+        }
+    }
+}
+
+/// One training position: the network input, the search's visit distribution over columns and
+/// the final result of the game for the player to move.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub input: Vec<f32>,
+    pub policy: Vec<f32>,
+    pub value: f32,
+}
+
+impl Sample {
+    /// The same position and targets reflected left to right.
+    pub fn mirrored(&self, w: u32, h: u32) -> Sample {
+        let (w, h) = (w as usize, h as usize);
+        let cells = w * h;
+        let mut input = self.input.clone();
+        for plane in 0..2 {
+            for row in 0..h {
+                for column in 0..w {
+                    input[plane * cells + row * w + column] = self.input[plane * cells + row * w + w - 1 - column];
+                }
+            }
+        }
+        Sample {
+            input: input,
+            policy: self.policy.iter().rev().cloned().collect(),
+            value: self.value,
+        }
+    }
+}
+
+/// What one iteration did.
+#[derive(Clone, Debug)]
+pub struct IterationReport {
+    pub iteration: u32,
+    pub samples: usize,
+    /// Mean loss over the last training epoch.
+    pub loss: f32,
+    /// The new network's score against the previous best.
+    pub gating_score: f32,
+    pub accepted: bool,
+}
+
+/// An AlphaZero-style training loop: the best network so far plays itself with PUCT, a copy is
+/// trained on the recorded positions, and the copy replaces the best network if it wins a
+/// head-to-head match.
+pub struct Trainer {
+    config: TrainingConfig,
+    best: Network,
+    replay: VecDeque<Sample>,
+    iteration: u32,
+}
+
+impl Trainer {
+    /// Starts a run, or resumes the one checkpointed in `config.checkpoint_dir`.
+    pub fn new(config: TrainingConfig) -> io::Result<Trainer> {
+        let mut trainer = Trainer {
+            best: Network::new(config.w, config.h, &config.hidden, [config.seed, 7, 11, 13]),
+            replay: VecDeque::new(),
+            iteration: 0,
+            config: config,
+        };
+        if let Some(dir) = trainer.config.checkpoint_dir.clone() {
+            fs::create_dir_all(&dir)?;
+            let best = dir.join("best.c4nn");
+            if best.exists() {
+                trainer.best = Network::load(best)?;
+                if trainer.best.width() != trainer.config.w || trainer.best.height() != trainer.config.h {
+                    return Err(invalid("checkpoint is for another board size"));
+                }
+            }
+            let replay = dir.join("replay.bin");
+            if replay.exists() {
+                let (iteration, samples) = read_replay(&mut BufReader::new(File::open(replay)?), &trainer.config)?;
+                trainer.iteration = iteration;
+                trainer.replay = samples.into_iter().collect();
+            }
+        }
+        Ok(trainer)
+    }
+
+    pub fn best(&self) -> &Network {
+        &self.best
+    }
+
+    /// Iterations completed, including those of a resumed run.
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    pub fn run_iteration(&mut self) -> io::Result<IterationReport> {
+        let config = self.config.clone();
+        let seed = config.seed.wrapping_mul(1000).wrapping_add(self.iteration);
+
+        let player = Arc::new(self.best.clone());
+        for game in 0..config.games_per_iteration {
+            for sample in self_play_game(&player, &config, [seed, game + 1, 17, 19]) {
+                let mirrored = sample.mirrored(config.w, config.h);
+                self.replay.push_back(sample);
+                self.replay.push_back(mirrored);
+            }
+        }
+        while self.replay.len() > config.replay_size {
+            self.replay.pop_front();
+        }
+
+        let mut candidate = self.best.clone();
+        let samples: Vec<Sample> = self.replay.iter().cloned().collect();
+        let mut rng: XorShiftRng = SeedableRng::from_seed([seed, 23, 29, 31]);
+        let mut loss = 0.;
+        for _ in 0..config.epochs {
+            loss = train_epoch(&mut candidate, &samples, config.learning_rate, config.batch_size, &mut rng);
+        }
+
+        let candidate = Arc::new(candidate);
+        let gating_score = arena(&candidate, &player, &config, [seed, 37, 41, 43]);
+        let accepted = gating_score >= config.gating_threshold;
+        if accepted {
+            self.best = (*candidate).clone();
+        }
+        self.iteration += 1;
+
+        let report = IterationReport {
+            iteration: self.iteration,
+            samples: samples.len(),
+            loss: loss,
+            gating_score: gating_score,
+            accepted: accepted,
+        };
+        if let Some(ref dir) = config.checkpoint_dir {
+            candidate.save(dir.join(format!("candidate-{:04}.c4nn", self.iteration)))?;
+            self.best.save(dir.join("best.c4nn"))?;
+            let mut out = BufWriter::new(File::create(dir.join("replay.bin"))?);
+            write_replay(&mut out, &config, self.iteration, &samples)?;
+            out.flush()?;
+            let mut log = OpenOptions::new().create(true).append(true).open(dir.join("log.txt"))?;
+            writeln!(
+                log,
+                "iteration {} samples {} loss {:.4} gating {:.3} {}",
+                report.iteration, report.samples, report.loss, report.gating_score,
+                if accepted { "accepted" } else { "rejected" }
+            )?;
+        }
+        Ok(report)
+    }
+}
+
+/// Plays one game of `network` against itself with PUCT and records every position.
+pub fn self_play_game(network: &Arc<Network>, config: &TrainingConfig, seed: [u32; 4]) -> Vec<Sample> {
+    let mut rng: XorShiftRng = SeedableRng::from_seed(seed);
+    let mut board = Board::from_int_array(vec![0; (config.w * config.h) as usize], config.w, config.h);
+    let mut turn = Player::P1;
+    let mut positions = Vec::new();
+
+    let winner = loop {
+        let mut tree = UctTree::new(&board, turn, [seed[0], seed[1], board.turn_number + 1, 0x2545]);
+        tree.set_exploration(config.exploration);
+        tree.set_evaluator(network.clone());
+        tree.run(config.playouts);
+
+        let stats = tree.root_stats();
+        let best = tree.best_move().expect("no move found");
+        let mut policy = vec![0.; config.w as usize];
+        let visits: u32 = stats.iter().map(|s| s.visits).sum();
+        if visits == 0 || tree.proven().is_some() {
+            policy[best as usize] = 1.;
+        } else {
+            for s in &stats {
+                policy[s.column as usize] = s.visits as f32 / visits as f32;
+            }
+        }
+        positions.push((network.encode(&board, turn), policy.clone(), turn));
+
+        let column = if board.turn_number < config.sampled_plies {
+            sample_column(&policy, &mut rng)
+        } else {
+            best
+        };
+        board = board.place(column, turn).unwrap();
+        if board.is_over(column) {
+            break turn;
+        }
+        if board.turn_number >= config.w * config.h {
+            break Player::Empty;
+        }
+        turn = turn.switch();
+    };
+
+    positions
+        .into_iter()
+        .map(|(input, policy, turn)| Sample {
+            input: input,
+            policy: policy,
+            value: if winner == Player::Empty { 0. } else if winner == turn { 1. } else { -1. },
+        })
+        .collect()
+}
+
+fn sample_column(policy: &[f32], rng: &mut XorShiftRng) -> u32 {
+    let mut x = rng.gen_range(0., 1.);
+    for (column, &p) in policy.iter().enumerate() {
+        if x < p {
+            return column as u32;
+        }
+        x -= p;
+    }
+    //rounding left a little over, take the last legal column
+    policy.iter().rposition(|&p| p > 0.).unwrap() as u32
+}
+
+/// Plays `config.gating_games` games between `a` and `b`, alternating colours, and returns
+/// `a`'s score: 1 per win and a half per draw, over the number of games. The first two plies
+/// of every game are random so the games differ.
+pub fn arena(a: &Arc<Network>, b: &Arc<Network>, config: &TrainingConfig, seed: [u32; 4]) -> f32 {
+    let mut rng: XorShiftRng = SeedableRng::from_seed(seed);
+    let mut score = 0.;
+    for game in 0..config.gating_games {
+        let a_player = if game % 2 == 0 { Player::P1 } else { Player::P2 };
+        let mut board = Board::from_int_array(vec![0; (config.w * config.h) as usize], config.w, config.h);
+        let mut turn = Player::P1;
+        let winner = loop {
+            let column = if board.turn_number < 2 {
+                let legal: Vec<u32> = (0..config.w).filter(|&c| board.place(c, turn).is_some()).collect();
+                legal[rng.gen_range(0, legal.len())]
+            } else {
+                let network = if turn == a_player { a } else { b };
+                let mut tree = UctTree::new(&board, turn, [seed[0], game + 1, board.turn_number + 1, 0x2545]);
+                tree.set_exploration(config.exploration);
+                tree.set_evaluator(network.clone());
+                tree.run(config.playouts);
+                tree.best_move().expect("no move found")
+            };
+            board = board.place(column, turn).unwrap();
+            if board.is_over(column) {
+                break turn;
+            }
+            if board.turn_number >= config.w * config.h {
+                break Player::Empty;
+            }
+            turn = turn.switch();
+        };
+        if winner == a_player {
+            score += 1.;
+        } else if winner == Player::Empty {
+            score += 0.5;
+        }
+    }
+    score / config.gating_games as f32
+}
+
+/// One pass of minibatch gradient descent over `samples` in a random order. Returns the mean
+/// loss over the pass: squared value error plus policy cross-entropy.
+pub fn train_epoch(network: &mut Network, samples: &[Sample], learning_rate: f32, batch_size: usize, rng: &mut XorShiftRng) -> f32 {
+    let mut order: Vec<usize> = (0..samples.len()).collect();
+    rng.shuffle(&mut order);
+
+    let mut total = 0.;
+    for batch in order.chunks(batch_size.max(1)) {
+        let mut gradients = Gradients::zero(network);
+        for &i in batch {
+            total += backprop(network, &samples[i], &mut gradients);
+        }
+        gradients.apply(network, learning_rate / batch.len() as f32);
+    }
+    if samples.is_empty() { 0. } else { total / samples.len() as f32 }
+}
+
+/// The loss of `network` on one sample.
+pub fn loss(network: &Network, sample: &Sample) -> f32 {
+    backprop(network, sample, &mut Gradients::zero(network))
+}
+
+//weight and bias gradients for every layer: the hidden layers, then the value and policy heads
+struct Gradients {
+    layers: Vec<(Vec<f32>, Vec<f32>)>,
+}
+
+impl Gradients {
+    fn zero(network: &Network) -> Gradients {
+        Gradients {
+            layers: layers(network)
+                .iter()
+                .map(|l| (vec![0.; l.weights.len()], vec![0.; l.biases.len()]))
+                .collect(),
+        }
+    }
+
+    //adds the gradient of a layer whose outputs had error `delta` for inputs `input`
+    fn add(&mut self, layer: usize, delta: &[f32], input: &[f32]) {
+        let (ref mut weights, ref mut biases) = self.layers[layer];
+        for (o, &d) in delta.iter().enumerate() {
+            biases[o] += d;
+            for (w, &x) in weights[o * input.len()..(o + 1) * input.len()].iter_mut().zip(input) {
+                *w += d * x;
+            }
+        }
+    }
+
+    fn apply(&self, network: &mut Network, step: f32) {
+        let mut layers: Vec<&mut Dense> = network.hidden.iter_mut().collect();
+        layers.push(&mut network.value);
+        layers.push(&mut network.policy);
+        for (layer, &(ref weights, ref biases)) in layers.into_iter().zip(&self.layers) {
+            for (w, g) in layer.weights.iter_mut().zip(weights) {
+                *w -= step * g;
+            }
+            for (b, g) in layer.biases.iter_mut().zip(biases) {
+                *b -= step * g;
+            }
+        }
+    }
+}
+
+fn layers(network: &Network) -> Vec<&Dense> {
+    let mut layers: Vec<&Dense> = network.hidden.iter().collect();
+    layers.push(&network.value);
+    layers.push(&network.policy);
+    layers
+}
+
+//the error at a layer's inputs, given the error at its outputs
+fn backward(layer: &Dense, delta: &[f32]) -> Vec<f32> {
+    let mut input = vec![0.; layer.inputs];
+    for (o, &d) in delta.iter().enumerate() {
+        for (x, &w) in input.iter_mut().zip(&layer.weights[o * layer.inputs..(o + 1) * layer.inputs]) {
+            *x += d * w;
+        }
+    }
+    input
+}
+
+//adds the gradients of the loss on `sample` to `gradients` and returns the loss
+fn backprop(network: &Network, sample: &Sample, gradients: &mut Gradients) -> f32 {
+    let mut activations = vec![sample.input.clone()];
+    for layer in &network.hidden {
+        let z = layer.forward(activations.last().unwrap(), 1);
+        activations.push(z.into_iter().map(|v| v.max(0.)).collect());
+    }
+    let hidden = network.hidden.len();
+    let features = activations[hidden].clone();
+
+    let value = network.value.forward(&features, 1)[0].tanh();
+    let logits = network.policy.forward(&features, 1);
+    let max = logits.iter().cloned().fold(::std::f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = logits.iter().map(|l| (l - max).exp()).collect();
+    let total: f32 = exps.iter().sum();
+    let probabilities: Vec<f32> = exps.iter().map(|e| e / total).collect();
+
+    let error = value - sample.value;
+    let mut loss = error * error;
+    for (p, &t) in probabilities.iter().zip(&sample.policy) {
+        if t > 0. {
+            loss -= t * p.max(1e-12).ln();
+        }
+    }
+
+    let value_delta = [2. * error * (1. - value * value)];
+    let policy_delta: Vec<f32> = probabilities.iter().zip(&sample.policy).map(|(p, t)| p - t).collect();
+    gradients.add(hidden, &value_delta, &features);
+    gradients.add(hidden + 1, &policy_delta, &features);
+
+    let mut delta: Vec<f32> = backward(&network.value, &value_delta)
+        .into_iter()
+        .zip(backward(&network.policy, &policy_delta))
+        .map(|(a, b)| a + b)
+        .collect();
+    for l in (0..hidden).rev() {
+        //through the ReLU, whose output is positive exactly where its gradient is 1
+        for (d, &a) in delta.iter_mut().zip(&activations[l + 1]) {
+            if a <= 0. {
+                *d = 0.;
+            }
+        }
+        gradients.add(l, &delta, &activations[l]);
+        if l > 0 {
+            delta = backward(&network.hidden[l], &delta);
+        }
+    }
+    loss
+}
+
+fn write_replay<W: Write>(out: &mut W, config: &TrainingConfig, iteration: u32, samples: &[Sample]) -> io::Result<()> {
+    out.write_all(REPLAY_MAGIC)?;
+    out.write_all(&[REPLAY_VERSION, config.w as u8, config.h as u8, 0])?;
+    out.write_all(&u32_bytes(iteration))?;
+    out.write_all(&u32_bytes(samples.len() as u32))?;
+    for sample in samples {
+        for &x in sample.input.iter().chain(&sample.policy).chain(Some(&sample.value)) {
+            out.write_all(&u32_bytes(x.to_bits()))?;
+        }
+    }
+    Ok(())
+}
+
+fn read_replay<R: Read>(input: &mut R, config: &TrainingConfig) -> io::Result<(u32, Vec<Sample>)> {
+    let mut header = [0; 16];
+    input.read_exact(&mut header)?;
+    if &header[..4] != REPLAY_MAGIC || header[4] != REPLAY_VERSION {
+        return Err(invalid("not a replay buffer"));
+    }
+    if header[5] as u32 != config.w || header[6] as u32 != config.h {
+        return Err(invalid("replay buffer is for another board size"));
+    }
+    let iteration = from_u32_bytes(&header[8..12]);
+    let count = from_u32_bytes(&header[12..16]);
+
+    let inputs = (2 * config.w * config.h) as usize;
+    let mut samples = Vec::new();
+    let mut bytes = [0; 4];
+    for _ in 0..count {
+        let mut values = Vec::with_capacity(inputs + config.w as usize + 1);
+        for _ in 0..inputs + config.w as usize + 1 {
+            input.read_exact(&mut bytes)?;
+            values.push(f32::from_bits(from_u32_bytes(&bytes)));
+        }
+        let value = values.pop().unwrap();
+        let policy = values.split_off(inputs);
+        samples.push(Sample {
+            input: values,
+            policy: policy,
+            value: value,
+        });
+    }
+    Ok((iteration, samples))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    fn tiny_config() -> TrainingConfig {
+        TrainingConfig {
+            w: 4,
+            h: 4,
+            hidden: vec![16],
+            games_per_iteration: 2,
+            playouts: 20,
+            epochs: 1,
+            gating_games: 2,
+            ..TrainingConfig::default()
+        }
+    }
+
+    fn sample(network: &Network, moves: &[u32], policy: Vec<f32>, value: f32) -> Sample {
+        let (board, turn) = Board::from_moves(4, 4, moves).unwrap();
+        Sample {
+            input: network.encode(&board, turn),
+            policy: policy,
+            value: value,
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let network = Network::new(4, 4, &[6, 5], [1, 2, 3, 4]);
+        let s = sample(&network, &[1, 2, 2], vec![0.1, 0.2, 0.3, 0.4], -1.);
+        let mut gradients = Gradients::zero(&network);
+        backprop(&network, &s, &mut gradients);
+
+        let eps = 1e-2;
+        for layer in 0..4 {
+            for &i in &[0, 3] {
+                let nudged = |delta: f32| {
+                    let mut n = network.clone();
+                    {
+                        let mut layers: Vec<&mut Dense> = n.hidden.iter_mut().collect();
+                        layers.push(&mut n.value);
+                        layers.push(&mut n.policy);
+                        layers[layer].weights[i] += delta;
+                    }
+                    loss(&n, &s)
+                };
+                let numeric = (nudged(eps) - nudged(-eps)) / (2. * eps);
+                let analytic = gradients.layers[layer].0[i];
+                assert!((numeric - analytic).abs() < 1e-2 + 0.05 * analytic.abs(), "layer {} weight {}: {} vs {}", layer, i, numeric, analytic);
+            }
+        }
+    }
+
+    #[test]
+    fn training_fits_a_few_samples() {
+        let mut network = Network::new(4, 4, &[16], [1, 2, 3, 4]);
+        let samples = vec![
+            sample(&network, &[], vec![0., 1., 0., 0.], 1.),
+            sample(&network, &[1], vec![0., 0., 0., 1.], -1.),
+            sample(&network, &[1, 2], vec![0.5, 0.5, 0., 0.], 0.),
+        ];
+        let before: f32 = samples.iter().map(|s| loss(&network, s)).sum();
+        let mut rng = SeedableRng::from_seed([1, 2, 3, 4]);
+        for _ in 0..200 {
+            train_epoch(&mut network, &samples, 0.05, 2, &mut rng);
+        }
+        let after: f32 = samples.iter().map(|s| loss(&network, s)).sum();
+        assert!(after < before / 4., "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn self_play_records_every_position() {
+        let config = tiny_config();
+        let network = Arc::new(Network::new(4, 4, &[16], [1, 2, 3, 4]));
+        let samples = self_play_game(&network, &config, [1, 2, 3, 4]);
+        assert!(samples.len() >= 7 && samples.len() <= 16);
+        for s in &samples {
+            assert!((s.policy.iter().sum::<f32>() - 1.).abs() < 1e-5);
+        }
+        //the result alternates with the side to move, unless the game was drawn
+        for pair in samples.windows(2) {
+            assert_eq!(pair[0].value, -pair[1].value);
+        }
+
+        let mirrored = samples[1].mirrored(4, 4);
+        assert_eq!(mirrored.mirrored(4, 4), samples[1]);
+        assert_eq!(mirrored.policy[0], samples[1].policy[3]);
+    }
+
+    #[test]
+    fn iterations_checkpoint_and_resume() {
+        let dir = env::temp_dir().join(format!("c4-training-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config = TrainingConfig {
+            checkpoint_dir: Some(dir.clone()),
+            ..tiny_config()
+        };
+
+        let mut trainer = Trainer::new(config.clone()).unwrap();
+        let report = trainer.run_iteration().unwrap();
+        assert_eq!(report.iteration, 1);
+        assert!(report.samples > 0);
+        assert!(report.gating_score >= 0. && report.gating_score <= 1.);
+        assert!(dir.join("best.c4nn").exists());
+        assert!(dir.join("candidate-0001.c4nn").exists());
+
+        let resumed = Trainer::new(config).unwrap();
+        assert_eq!(resumed.iteration(), 1);
+        assert_eq!(resumed.replay.len(), report.samples);
+        assert!(resumed.best() == trainer.best());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
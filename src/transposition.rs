@@ -0,0 +1,156 @@
+use c4::*;
+
+/// Default table size used by `mcts::mcts`, in bytes.
+pub const DEFAULT_TABLE_SIZE: usize = 16 * 1024 * 1024;
+
+/// How a stored value relates to the true value of the position.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Entry {
+    pub key: u64,
+    pub bound: Bound,
+    pub depth: i32,
+    pub value: f32,
+    pub best_move: Option<u32>,
+    /// Whether `value` was backed up from finished games only, and so holds at any depth.
+    pub proven: bool,
+    generation: u8,
+}
+
+/// A fixed-size table of search results keyed on `position_key`.
+///
+/// Each key maps to a single slot. A new result replaces the slot's entry if the entry is for
+/// the same position, was left over from an earlier search, or was searched no deeper.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    generation: u8,
+}
+
+impl TranspositionTable {
+    /// Creates a table using roughly `size_in_bytes` of memory, with room for at least one entry.
+    pub fn new(size_in_bytes: usize) -> TranspositionTable {
+        let slot_size = ::std::mem::size_of::<Option<Entry>>();
+        let slots = (size_in_bytes / slot_size).max(1);
+        TranspositionTable {
+            entries: vec![None; slots],
+            generation: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        match self.entries[self.slot(key)] {
+            Some(entry) if entry.key == key => Some(entry),
+            _ => None,
+        }
+    }
+
+    pub fn store(
+        &mut self,
+        key: u64,
+        bound: Bound,
+        depth: i32,
+        value: f32,
+        best_move: Option<u32>,
+        proven: bool,
+    ) {
+        let slot = self.slot(key);
+        let replace = match self.entries[slot] {
+            None => true,
+            Some(ref old) => {
+                old.key == key || old.generation != self.generation || depth >= old.depth
+            }
+        };
+        if replace {
+            self.entries[slot] = Some(Entry {
+                key: key,
+                bound: bound,
+                depth: depth,
+                value: value,
+                best_move: best_move,
+                proven: proven,
+                generation: self.generation,
+            });
+        }
+    }
+
+    /// Marks every current entry as belonging to an earlier search, so new results may
+    /// overwrite them regardless of depth. Entries stay available for probing.
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+}
+
+/// The key of `board` with `turn` to move.
+pub fn position_key(board: &Board, turn: Player) -> u64 {
+    match turn {
+        Player::P2 => board.hash() ^ 0x9e37_79b9_7f4a_7c15,
+        _ => board.hash(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn store_and_probe() {
+        let mut table = TranspositionTable::new(1024);
+        table.store(42, Bound::Exact, 3, 0.5, Some(2), false);
+
+        let entry = table.probe(42).expect("entry missing");
+        assert_eq!(entry.bound, Bound::Exact);
+        assert_eq!(entry.depth, 3);
+        assert_eq!(entry.value, 0.5);
+        assert_eq!(entry.best_move, Some(2));
+        assert!(!entry.proven);
+        assert!(table.probe(43).is_none());
+    }
+
+    #[test]
+    fn deeper_entries_survive_within_a_search() {
+        let mut table = TranspositionTable::new(1);
+        assert_eq!(table.capacity(), 1);
+
+        table.store(1, Bound::Exact, 4, 0.5, None, false);
+        table.store(2, Bound::Exact, 2, 0.1, None, false);
+        assert!(table.probe(1).is_some());
+        assert!(table.probe(2).is_none());
+
+        table.new_search();
+        table.store(2, Bound::Exact, 2, 0.1, None, false);
+        assert!(table.probe(1).is_none());
+        assert!(table.probe(2).is_some());
+    }
+
+    #[test]
+    fn key_depends_on_pieces_and_turn() {
+        let a = Board::from_int_array(vec![1, 0, 0, 0], 4, 1);
+        let b = Board::from_int_array(vec![0, 1, 0, 0], 4, 1);
+        let c = Board::from_int_array(vec![-1, 0, 0, 0], 4, 1);
+
+        assert_eq!(position_key(&a, Player::P1), position_key(&a.clone(), Player::P1));
+        assert!(position_key(&a, Player::P1) != position_key(&b, Player::P1));
+        assert!(position_key(&a, Player::P1) != position_key(&c, Player::P1));
+        assert!(position_key(&a, Player::P1) != position_key(&a, Player::P2));
+    }
+}
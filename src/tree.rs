@@ -62,6 +62,8 @@ impl InnerNode {
 #[cfg(test)]
 mod test {
     use super::*;
+    use tablebase::Tablebase;
+    use mcts::Outcome;
 
     #[test]
     fn leaf_on_win() {
@@ -146,4 +148,29 @@ mod test {
             },
         }
     }
+
+    #[test]
+    fn children_agree_with_tablebase() {
+        let tablebase = Tablebase::build(4, 4);
+        for position in tablebase.positions() {
+            let turn = if position.moves() % 2 == 0 { Player::P1 } else { Player::P2 };
+            let mut node = InnerNode::new(position.to_board(turn), turn);
+            node.find_children();
+            let moves = tablebase.analyse(&node.board, turn).unwrap();
+            assert_eq!(node.children.len(), moves.len());
+            for (branch, &(column, solution)) in node.children.iter().zip(moves.iter()) {
+                assert_eq!(branch.input, column);
+                match branch.node {
+                    Node::Leaf(ref leaf) if leaf.winner == turn => {
+                        assert_eq!((solution.outcome, solution.plies), (Outcome::Win, 1))
+                    }
+                    Node::Leaf(ref leaf) => {
+                        assert_eq!(leaf.winner, Player::Empty);
+                        assert_eq!((solution.outcome, solution.plies), (Outcome::Draw, 1));
+                    }
+                    Node::InnerNode(_) => assert!(solution.plies > 1),
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,585 @@
+use c4::*;
+use tree::*;
+use rollout::*;
+use mcts::Outcome;
+use evaluator::Evaluator;
+use cancel::CancelToken;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Exploration constant used by `UctTree::new`.
+pub const DEFAULT_EXPLORATION: f32 = 1.4;
+
+/// Visit statistics for one move from the root.
+#[derive(Clone, Debug)]
+pub struct EdgeStats {
+    pub column: u32,
+    pub visits: u32,
+    /// Mean result for the player making the move: 1 is a win, 0 a loss.
+    pub value: f32,
+    /// The result of the move for the player making it, once every line after it is decided.
+    pub proven: Option<Outcome>,
+}
+
+/// A Monte Carlo search tree using UCB1 selection and random rollouts.
+///
+/// Finished games are propagated up the tree as proven outcomes, MCTS-Solver style: a node is
+/// won if any move wins, and lost or drawn once every move is decided. Proven nodes return
+/// their result without a rollout, and moves proven lost are never selected.
+///
+/// With a RAVE equivalence `k` above 0, every move also collects all-moves-as-first statistics:
+/// the results of every playout through its node in which its player played the same column
+/// later on. Selection blends them into the move's value with weight `sqrt(k / (3n + k))` after
+/// `n` visits, so they guide the early visits and fade out as real statistics build up. An
+/// untried move with all-moves-as-first results is judged by them alone rather than tried at once.
+///
+/// With an evaluator the tree runs PUCT instead: new nodes are scored by the evaluator rather
+/// than a rollout, and a move's exploration bonus is `exploration * prior * sqrt(N) / (1 + n)`,
+/// `N` being the visits of its node, so moves the evaluator likes are tried first.
+pub struct UctTree {
+    root: UctNode,
+    rng: XorShiftRng,
+    exploration: f32,
+    rave_equivalence: f32,
+    evaluator: Option<Arc<Evaluator>>,
+    cancel: Option<CancelToken>,
+    playouts: u64,
+}
+
+//the search settings every node needs
+#[derive(Copy, Clone)]
+struct Settings<'a> {
+    exploration: f32,
+    equivalence: f32,
+    evaluator: Option<&'a Evaluator>,
+}
+
+struct UctNode {
+    board: Board,
+    turn: Player,
+    visits: u32,
+    edges: Vec<Edge>,
+    expanded: bool,
+    //for the player to move
+    proven: Option<Outcome>,
+}
+
+struct Edge {
+    input: u32,
+    visits: u32,
+    //sum of results for the player making this move, each in [0, 1]
+    total: f32,
+    //the same over playouts where the column was played later by the same player
+    amaf_visits: u32,
+    amaf_total: f32,
+    //the evaluator's prior, under PUCT
+    prior: f32,
+    target: Target,
+    //for the player making this move
+    proven: Option<Outcome>,
+}
+
+enum Target {
+    Terminal(Player),
+    Node(Box<UctNode>),
+}
+
+impl UctTree {
+    /// `seed` must not be all zeroes.
+    pub fn new(board: &Board, turn: Player, seed: [u32; 4]) -> UctTree {
+        UctTree {
+            root: UctNode::new(board.clone(), turn),
+            rng: SeedableRng::from_seed(seed),
+            exploration: DEFAULT_EXPLORATION,
+            rave_equivalence: 0.,
+            evaluator: None,
+            cancel: None,
+            playouts: 0,
+        }
+    }
+
+    pub fn set_exploration(&mut self, exploration: f32) {
+        self.exploration = exploration;
+    }
+
+    /// Roughly the number of visits at which a move's own value and its all-moves-as-first
+    /// value count equally. 0, the default, turns RAVE off.
+    pub fn set_rave_equivalence(&mut self, equivalence: f32) {
+        self.rave_equivalence = equivalence;
+    }
+
+    /// Switches the tree to PUCT selection, guided and scored by `evaluator`. Call before the
+    /// first playout.
+    pub fn set_evaluator(&mut self, evaluator: Arc<Evaluator>) {
+        self.evaluator = Some(evaluator);
+    }
+
+    /// Makes `run` and `run_until` return early once `cancel` is cancelled.
+    pub fn set_cancel(&mut self, cancel: CancelToken) {
+        self.cancel = Some(cancel);
+    }
+
+    pub fn playouts(&self) -> u64 {
+        self.playouts
+    }
+
+    /// Runs one selection, expansion, rollout and backup pass.
+    pub fn playout(&mut self) {
+        let rng = &mut self.rng;
+        let mut f = |x| rng.gen_range(0, x);
+        let mut moves = Vec::new();
+        let settings = Settings {
+            exploration: self.exploration,
+            equivalence: self.rave_equivalence,
+            evaluator: self.evaluator.as_ref().map(|e| &**e),
+        };
+        self.root.playout(settings, &mut f, &mut moves);
+        self.playouts += 1;
+    }
+
+    /// Runs `playouts` passes, or fewer if the root is proven or the search cancelled first.
+    pub fn run(&mut self, playouts: u32) {
+        for _ in 0..playouts {
+            if self.finished() {
+                return;
+            }
+            self.playout();
+        }
+    }
+
+    /// Runs passes until `deadline`, or until the root is proven or the search cancelled.
+    pub fn run_until(&mut self, deadline: Instant) {
+        while !self.finished() && Instant::now() < deadline {
+            self.playout();
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.root.proven.is_some() || self.cancel.as_ref().map_or(false, |c| c.is_cancelled())
+    }
+
+    /// The result for the player to move at the root, once the tree has proven it.
+    pub fn proven(&self) -> Option<Outcome> {
+        self.root.proven
+    }
+
+    pub fn root_stats(&self) -> Vec<EdgeStats> {
+        if !self.root.expanded {
+            return Vec::new();
+        }
+        self.root
+            .edges
+            .iter()
+            .map(|e| EdgeStats {
+                column: e.input,
+                visits: e.visits,
+                value: if e.visits == 0 { 0. } else { e.total / e.visits as f32 },
+                proven: e.proven,
+            })
+            .collect()
+    }
+
+    /// The move `pick_move` chooses from the root, once at least one playout has run.
+    pub fn best_move(&self) -> Option<u32> {
+        pick_move(&self.root_stats())
+    }
+}
+
+/// A move proven to win if there is one, otherwise the most visited move not proven to lose.
+pub fn pick_move(stats: &[EdgeStats]) -> Option<u32> {
+    if let Some(s) = stats.iter().find(|s| s.proven == Some(Outcome::Win)) {
+        return Some(s.column);
+    }
+    let open: Vec<EdgeStats> = stats.iter().filter(|s| s.proven != Some(Outcome::Loss)).cloned().collect();
+    most_visited(if open.is_empty() { stats } else { &open })
+}
+
+/// The result for the player choosing among `stats`, if their proven outcomes settle it.
+pub fn proven_outcome(stats: &[EdgeStats]) -> Option<Outcome> {
+    combine(stats.iter().map(|s| s.proven))
+}
+
+//won if any move wins, otherwise the best result once every move is decided
+fn combine<I: Iterator<Item = Option<Outcome>>>(outcomes: I) -> Option<Outcome> {
+    let mut best = Some(Outcome::Loss);
+    for outcome in outcomes {
+        match outcome {
+            Some(Outcome::Win) => return Some(Outcome::Win),
+            Some(Outcome::Draw) => best = best.map(|_| Outcome::Draw),
+            Some(Outcome::Loss) => (),
+            None => best = None,
+        }
+    }
+    best
+}
+
+/// The most visited move in `stats`, ties going to the earlier column.
+pub fn most_visited(stats: &[EdgeStats]) -> Option<u32> {
+    let mut best: Option<&EdgeStats> = None;
+    for s in stats {
+        match best {
+            Some(b) if b.visits >= s.visits => (),
+            _ => best = Some(s),
+        }
+    }
+    best.map(|s| s.column)
+}
+
+impl UctNode {
+    fn new(board: Board, turn: Player) -> UctNode {
+        UctNode {
+            board: board,
+            turn: turn,
+            visits: 0,
+            edges: Vec::new(),
+            expanded: false,
+            proven: None,
+        }
+    }
+
+    fn expand(&mut self) {
+        let mut inner = InnerNode::new(self.board.clone(), self.turn);
+        inner.find_children();
+        let turn = self.turn;
+        self.edges = inner
+            .children
+            .into_iter()
+            .map(|branch| match branch.node {
+                Node::Leaf(l) => Edge {
+                    input: branch.input,
+                    visits: 0,
+                    total: 0.,
+                    amaf_visits: 0,
+                    amaf_total: 0.,
+                    prior: 0.,
+                    target: Target::Terminal(l.winner),
+                    proven: Some(if l.winner == turn { Outcome::Win } else { Outcome::Draw }),
+                },
+                Node::InnerNode(n) => Edge {
+                    input: branch.input,
+                    visits: 0,
+                    total: 0.,
+                    amaf_visits: 0,
+                    amaf_total: 0.,
+                    prior: 0.,
+                    target: Target::Node(Box::new(UctNode::new(n.board, n.turn))),
+                    proven: None,
+                },
+            })
+            .collect();
+        self.expanded = true;
+        self.update_proven();
+    }
+
+    fn update_proven(&mut self) {
+        self.proven = combine(self.edges.iter().map(|e| e.proven));
+    }
+
+    /// Returns the result of the playout for P1: 1 for a win, -1 for a loss, 0 for a draw.
+    /// Appends the columns played from this node on to `moves`.
+    fn playout(&mut self, settings: Settings, rng: &mut FnMut(u32) -> u32, moves: &mut Vec<u32>) -> f32 {
+        if !self.expanded {
+            self.expand();
+            self.visits += 1;
+            if let Some(outcome) = self.proven {
+                return self.outcome_score(outcome);
+            }
+            if let Some(evaluator) = settings.evaluator {
+                let evaluation = evaluator.evaluate(&self.board, self.turn);
+                for edge in self.edges.iter_mut() {
+                    edge.prior = evaluation.priors[edge.input as usize];
+                }
+                return evaluation.value * self.turn.sign();
+            }
+            return recorded_rollout(&self.board, &self.turn, rng, moves);
+        }
+        if let Some(outcome) = self.proven {
+            self.visits += 1;
+            return self.outcome_score(outcome);
+        }
+
+        let i = self.select(settings);
+        let start = moves.len();
+        moves.push(self.edges[i].input);
+        let result = {
+            let edge = &mut self.edges[i];
+            match edge.target {
+                Target::Terminal(winner) => winner.sign(),
+                Target::Node(ref mut node) => {
+                    let result = node.playout(settings, rng, moves);
+                    edge.proven = node.proven.map(flip);
+                    result
+                }
+            }
+        };
+
+        let score = (1. + result * self.turn.sign()) / 2.;
+        if settings.equivalence > 0. {
+            self.update_amaf(&moves[start..], score);
+        }
+        let edge = &mut self.edges[i];
+        edge.visits += 1;
+        edge.total += score;
+        self.visits += 1;
+        if edge.proven.is_some() {
+            self.update_proven();
+        }
+        result
+    }
+
+    //credits every column this node's player played in `moves`, once each
+    fn update_amaf(&mut self, moves: &[u32], score: f32) {
+        let mut seen = vec![false; self.board.w as usize];
+        for &column in moves.iter().step_by(2) {
+            if seen[column as usize] {
+                continue;
+            }
+            seen[column as usize] = true;
+            if let Some(edge) = self.edges.iter_mut().find(|e| e.input == column) {
+                edge.amaf_visits += 1;
+                edge.amaf_total += score;
+            }
+        }
+    }
+
+    //the result for P1 of the game ending with `outcome` for the player to move
+    fn outcome_score(&self, outcome: Outcome) -> f32 {
+        match outcome {
+            Outcome::Win => self.turn.sign(),
+            Outcome::Loss => -self.turn.sign(),
+            Outcome::Draw => 0.,
+        }
+    }
+
+    fn select(&self, settings: Settings) -> usize {
+        if settings.evaluator.is_some() {
+            return self.select_puct(settings);
+        }
+        let (exploration, equivalence) = (settings.exploration, settings.equivalence);
+        let log_visits = (self.visits.max(1) as f32).ln();
+        let mut best = 0;
+        let mut best_value = ::std::f32::NEG_INFINITY;
+        for (i, edge) in self.edges.iter().enumerate() {
+            if edge.proven == Some(Outcome::Loss) {
+                continue;
+            }
+            //a move with no statistics at all, real or all-moves-as-first, is tried first
+            if edge.visits == 0 && (equivalence <= 0. || edge.amaf_visits == 0) {
+                return i;
+            }
+            let n = edge.visits.max(1) as f32;
+            let value = self.mean(edge, equivalence) + exploration * (log_visits / n).sqrt();
+            if value > best_value {
+                best_value = value;
+                best = i;
+            }
+        }
+        best
+    }
+
+    fn select_puct(&self, settings: Settings) -> usize {
+        let sqrt_visits = (self.visits as f32).sqrt();
+        let mut best = 0;
+        let mut best_value = ::std::f32::NEG_INFINITY;
+        for (i, edge) in self.edges.iter().enumerate() {
+            if edge.proven == Some(Outcome::Loss) {
+                continue;
+            }
+            //unvisited moves count as even until tried
+            let mean = if edge.visits == 0 { 0.5 } else { self.mean(edge, settings.equivalence) };
+            let value = mean + settings.exploration * edge.prior * sqrt_visits / (1. + edge.visits as f32);
+            if value > best_value {
+                best_value = value;
+                best = i;
+            }
+        }
+        best
+    }
+
+    //the mean result of a move, blended with its all-moves-as-first mean under RAVE, which is
+    //all an unvisited move has to go on
+    fn mean(&self, edge: &Edge, equivalence: f32) -> f32 {
+        let n = edge.visits as f32;
+        if equivalence <= 0. || edge.amaf_visits == 0 {
+            return edge.total / n;
+        }
+        let beta = (equivalence / (3. * n + equivalence)).sqrt();
+        let own = if edge.visits == 0 { 0. } else { edge.total / n };
+        (1. - beta) * own + beta * edge.amaf_total / edge.amaf_visits as f32
+    }
+}
+
+fn flip(outcome: Outcome) -> Outcome {
+    match outcome {
+        Outcome::Win => Outcome::Loss,
+        Outcome::Loss => Outcome::Win,
+        Outcome::Draw => Outcome::Draw,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use evaluator::*;
+
+    #[test]
+    fn finds_the_winning_move() {
+        let board = Board::from_int_array(vec![-1, -1, 0, 0, 1, 1, 0], 7, 1);
+        let mut tree = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        tree.run(500);
+
+        assert_eq!(tree.best_move(), Some(3));
+        assert_eq!(tree.proven(), Some(Outcome::Win));
+    }
+
+    #[test]
+    fn counts_playouts() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let mut tree = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        tree.run(500);
+
+        assert_eq!(tree.playouts(), 500);
+        let visits: u32 = tree.root_stats().iter().map(|s| s.visits).sum();
+        assert_eq!(visits, 499);
+        assert_eq!(tree.proven(), None);
+    }
+
+    #[test]
+    fn proves_a_loss() {
+        let board = Board::from_int_array(vec![0, 1, 1, 1, 0], 5, 1);
+        let mut tree = UctTree::new(&board, Player::P2, [1, 2, 3, 4]);
+        tree.run(500);
+
+        assert_eq!(tree.proven(), Some(Outcome::Loss));
+        assert!(tree.root_stats().iter().all(|s| s.proven == Some(Outcome::Loss)));
+    }
+
+    #[test]
+    fn cancelled_trees_stop() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let mut tree = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        let cancel = CancelToken::new();
+        tree.set_cancel(cancel.clone());
+        tree.run(10);
+        assert_eq!(tree.playouts(), 10);
+        cancel.cancel();
+        tree.run(10);
+        tree.run_until(Instant::now() + ::std::time::Duration::from_secs(5));
+        assert_eq!(tree.playouts(), 10);
+    }
+
+    #[test]
+    fn stops_once_the_win_is_proven() {
+        //three in a row on the bottom with both ends open
+        let board = Board::from_int_array(vec![0; 42], 7, 6)
+            .place(2, Player::P1).unwrap()
+            .place(2, Player::P2).unwrap()
+            .place(3, Player::P1).unwrap()
+            .place(3, Player::P2).unwrap();
+        let mut tree = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        tree.run(20000);
+
+        assert_eq!(tree.proven(), Some(Outcome::Win));
+        assert!(tree.playouts() < 20000);
+        let best = tree.best_move().unwrap();
+        assert!(best == 1 || best == 4);
+    }
+
+    #[test]
+    fn blocks_for_p2() {
+        let board = Board::from_int_array(vec![1, 1, 1, 0, 0, 0, 0], 7, 1);
+        let mut tree = UctTree::new(&board, Player::P2, [5, 6, 7, 8]);
+        tree.run(500);
+
+        assert_eq!(tree.best_move(), Some(3));
+    }
+
+    #[test]
+    fn rave_counts_every_column_played() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let mut tree = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        tree.set_rave_equivalence(500.);
+        tree.run(300);
+
+        //each playout credits several root moves, but each at most once
+        let visits: u32 = tree.root.edges.iter().map(|e| e.visits).sum();
+        let amaf: u32 = tree.root.edges.iter().map(|e| e.amaf_visits).sum();
+        assert!(amaf > visits);
+        assert!(tree.root.edges.iter().all(|e| e.amaf_visits <= visits && e.amaf_visits >= e.visits));
+    }
+
+    #[test]
+    fn rave_finds_the_block() {
+        let board = Board::from_int_array(vec![1, 1, 1, 0, 0, 0, 0], 7, 1);
+        let mut tree = UctTree::new(&board, Player::P2, [5, 6, 7, 8]);
+        tree.set_rave_equivalence(500.);
+        tree.run(500);
+
+        assert_eq!(tree.best_move(), Some(3));
+    }
+
+    #[test]
+    fn rave_ranks_untried_moves() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let mut tree = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        tree.set_rave_equivalence(500.);
+        tree.run(1);
+        for (i, edge) in tree.root.edges.iter_mut().enumerate() {
+            edge.visits = 0;
+            edge.total = 0.;
+            edge.amaf_visits = 10;
+            edge.amaf_total = if i == 4 { 9. } else { 1. };
+        }
+        let settings = Settings {
+            exploration: DEFAULT_EXPLORATION,
+            equivalence: 500.,
+            evaluator: None,
+        };
+        assert_eq!(tree.root.select(settings), 4);
+
+        //a move nothing is known about still comes first
+        tree.root.edges[2].amaf_visits = 0;
+        assert_eq!(tree.root.select(settings), 2);
+    }
+
+    #[test]
+    fn puct_follows_the_evaluator() {
+        let board = Board::from_int_array(vec![0, 1, 1, 0, -1, -1, 0], 7, 1);
+        let mut tree = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        tree.set_evaluator(Arc::new(HeuristicEvaluator));
+        tree.run(100);
+        assert_eq!(tree.best_move(), Some(3));
+
+        //P2 has no win in one, the heuristic's block at 3 should get most of the visits
+        let board = Board::from_int_array(vec![0, 1, 1, 0, -1, 0, 0, 0, 0], 9, 1);
+        let mut tree = UctTree::new(&board, Player::P2, [1, 2, 3, 4]);
+        tree.set_evaluator(Arc::new(HeuristicEvaluator));
+        tree.run(200);
+        let stats = tree.root_stats();
+        let block = stats.iter().find(|s| s.column == 3).unwrap().visits;
+        assert!(stats.iter().all(|s| s.column == 3 || s.visits < block));
+    }
+
+    #[test]
+    fn puct_with_rollouts_finds_the_winning_move() {
+        let board = Board::from_int_array(vec![-1, -1, 0, 0, 1, 1, 0], 7, 1);
+        let mut tree = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        tree.set_evaluator(Arc::new(RolloutEvaluator { rollouts: 4, seed: 1 }));
+        tree.run(200);
+        assert_eq!(tree.best_move(), Some(3));
+    }
+
+    #[test]
+    fn same_seed_same_tree() {
+        let board = Board::from_int_array(vec![0; 42], 7, 6);
+        let mut a = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        let mut b = UctTree::new(&board, Player::P1, [1, 2, 3, 4]);
+        a.run(200);
+        b.run(200);
+
+        let visits = |t: &UctTree| t.root_stats().iter().map(|s| s.visits).collect::<Vec<_>>();
+        assert_eq!(visits(&a), visits(&b));
+    }
+}